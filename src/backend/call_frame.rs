@@ -0,0 +1,18 @@
+use crate::{common::gc::Gc, frontend::interpretation::closure::Closure};
+
+pub struct CallFrame {
+    pub closure: Gc<Closure>,
+    pub ip: usize,
+    // index of the stack slot holding the callee
+    pub slots: usize,
+}
+
+impl CallFrame {
+    pub fn new(closure: Gc<Closure>, slots: usize) -> Self {
+        Self {
+            closure,
+            ip: 0,
+            slots,
+        }
+    }
+}
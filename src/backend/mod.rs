@@ -1 +1,3 @@
+mod call_frame;
+mod stdlib;
 pub mod vm;
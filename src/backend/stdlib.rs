@@ -0,0 +1,36 @@
+use std::cmp::Ordering;
+
+use crate::frontend::interpretation::{literal::Literal, native::Native};
+
+pub fn natives() -> Vec<Native> {
+    vec![
+        Native::new("compare_ignore_case", 2, compare_ignore_case),
+        Native::new("equals_ignore_case", 2, equals_ignore_case),
+    ]
+}
+
+// -1, 0 or 1 like a comparator, case is folded the same way regardless of locale
+fn compare_ignore_case(args: &[Literal]) -> Result<Literal, String> {
+    let ordering = fold_case_cmp(&args[0], &args[1], "compare_ignore_case")?;
+    Ok(Literal::Int(ordering as isize))
+}
+
+fn equals_ignore_case(args: &[Literal]) -> Result<Literal, String> {
+    let ordering = fold_case_cmp(&args[0], &args[1], "equals_ignore_case")?;
+    Ok(Literal::Bool(ordering == Ordering::Equal))
+}
+
+fn fold_case_cmp(lhs: &Literal, rhs: &Literal, name: &str) -> Result<Ordering, String> {
+    let (Literal::String(lhs), Literal::String(rhs)) = (lhs, rhs) else {
+        return Err(format!(
+            "`{}` expected two strings but got {} and {}",
+            name,
+            lhs.type_name(),
+            rhs.type_name()
+        ));
+    };
+
+    let lhs = lhs.chars().flat_map(char::to_lowercase);
+    let rhs = rhs.chars().flat_map(char::to_lowercase);
+    Ok(lhs.cmp(rhs))
+}
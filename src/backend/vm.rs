@@ -1,37 +1,70 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, rc::Rc};
 
+use super::{call_frame::CallFrame, stdlib};
 use crate::{
-    common::chunk::Chunk,
-    error, error_at, error_line,
+    common::{
+        chunk::Chunk,
+        gc::{Gc, Heap, Trace, Tracer},
+        globals::GlobalNames,
+    },
+    error, error_at,
     frontend::{
         compiler::Compiler,
-        interpretation::{interpret_result::InterpretResult, literal::Literal, op_codes::OpCodes},
+        interpretation::{
+            class::{BoundMethod, Class, Instance},
+            closure::{Closure, Upvalue},
+            interpret_result::InterpretResult,
+            iter::Iter,
+            literal::Literal,
+            map::Map,
+            native::Native,
+            op_codes::OpCodes,
+            range::Range,
+        },
         lexer::Lexer,
-        tokenization::{location::Location, span::Span},
+        tokenization::span::Span,
     },
     parse_args::Options,
-    utils::debug::Debugger,
+    utils::{
+        debug::Debugger,
+        logger::{Level, Log},
+    },
 };
 
+const FRAMES_MAX: usize = 256;
+
 pub struct Vm {
     debugger: Debugger,
-    source_map: Rc<RefCell<Vec<Span>>>,
     stack: Vec<Literal>,
-    globals: HashMap<String, Literal>,
-    ip: usize,
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<Gc<RefCell<Upvalue>>>,
+    // indexed by the slots in `global_names`, none until the global is defined
+    globals: Vec<Option<Literal>>,
+    global_names: GlobalNames,
+    heap: Heap,
     options: Options,
 }
 
 impl Vm {
     pub fn new(options: Options) -> Self {
-        Self {
-            ip: 0,
+        let mut vm = Self {
             debugger: Debugger::new("debug_vm"),
-            source_map: Rc::new(RefCell::new(Vec::new())),
             stack: Vec::new(),
-            globals: HashMap::new(),
+            frames: Vec::new(),
+            open_upvalues: Vec::new(),
+            globals: Vec::new(),
+            global_names: GlobalNames::new(),
+            heap: Heap::new(),
             options,
+        };
+        for native in stdlib::natives() {
+            let name = vm.heap.intern(native.name);
+            let native = vm.heap.alloc(native);
+            let slot = vm.global_names.slot(name);
+            vm.globals.resize(slot + 1, None);
+            vm.globals[slot] = Some(Literal::Native(native));
         }
+        vm
     }
 
     pub fn interpret(&mut self, file_path: Rc<str>, source: String) -> InterpretResult {
@@ -39,28 +72,65 @@ impl Vm {
         let Ok(tokens) = lexer.tokens() else {
             return InterpretResult::CompileError;
         };
-        let mut compiler = Compiler::new(file_path.clone(), &tokens, self.source_map.clone());
-        let Ok(chunk) = compiler.compile() else {
+        let compiler = Compiler::new(
+            file_path.clone(),
+            &tokens,
+            &mut self.heap,
+            &mut self.global_names,
+        );
+        let Ok(function) = compiler.compile() else {
             error!("Couldn't run file due to error(s).");
             return InterpretResult::CompileError;
         };
+        self.globals.resize(self.global_names.len(), None);
         self.reset_stack();
-        return self.run(chunk);
+        let function = self.heap.alloc(function);
+        let closure = self.heap.alloc(Closure::new(function, Vec::new()));
+        self.stack.push(Literal::Closure(closure.clone()));
+        self.frames.push(CallFrame::new(closure, 0));
+
+        self.run(0)
     }
 
-    pub fn run(&mut self, chunk: &Chunk) -> InterpretResult {
+    // runs until the frame count drops back to `depth`, zero runs the whole script
+    fn run(&mut self, depth: usize) -> InterpretResult {
         loop {
+            // values only held by rust locals are unrooted, so only collect between instructions
+            if self.options.stress_gc || self.heap.should_collect() {
+                self.collect_garbage();
+            }
+            let function = self.frame().closure.function.clone();
+            let chunk = &function.chunk;
             if self.options.debug {
                 self.print_stack_slots();
-                self.debugger.disassemble_instruction(chunk, self.ip);
+                self.debugger
+                    .disassemble_instruction(chunk, self.frame().ip);
             }
-            let instruction = &chunk.code[self.ip];
-            match instruction {
+            let instruction = &chunk.code[self.frame().ip];
+            self.bump();
+            match *instruction {
                 OpCodes::Return => {
-                    return InterpretResult::Ok;
+                    let result = self.stack.pop().unwrap_or(Literal::None);
+                    let frame = self.frames.pop().expect("no call frame to return from");
+                    self.close_upvalues(frame.slots);
+                    if self.frames.is_empty() {
+                        self.stack.pop();
+                        return InterpretResult::Ok;
+                    }
+
+                    self.stack.truncate(frame.slots);
+                    self.stack.push(result);
+                    if self.frames.len() == depth {
+                        return InterpretResult::Ok;
+                    }
                 }
-                OpCodes::Constant(index) => {
-                    let constant = chunk.constants[*index].clone();
+                OpCodes::Call(operand) => {
+                    let Ok(_) = self.call_value(operand, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+                OpCodes::Constant(operand) => {
+                    let constant = chunk.constants[operand].clone();
                     self.stack.push(constant);
                 }
                 OpCodes::Negate => {
@@ -70,7 +140,7 @@ impl Vm {
                     self.stack.push(c)
                 }
                 OpCodes::Add => {
-                    let Ok(res) = self.binary(|l, r| l + r, chunk) else {
+                    let Ok(res) = self.add(chunk) else {
                         return InterpretResult::RuntimeError;
                     };
                     self.stack.push(res)
@@ -82,7 +152,7 @@ impl Vm {
                     self.stack.push(res)
                 }
                 OpCodes::Multiply => {
-                    let Ok(res) = self.binary(|l, r| l * r, chunk) else {
+                    let Ok(res) = self.multiply(chunk) else {
                         return InterpretResult::RuntimeError;
                     };
                     self.stack.push(res)
@@ -93,6 +163,48 @@ impl Vm {
                     };
                     self.stack.push(res)
                 }
+                OpCodes::Modulo => {
+                    let Ok(res) = self.binary(|l, r| l % r, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
+                OpCodes::BitAnd => {
+                    let Ok(res) = self.binary(|l, r| l & r, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
+                OpCodes::BitOr => {
+                    let Ok(res) = self.binary(|l, r| l | r, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
+                OpCodes::BitXor => {
+                    let Ok(res) = self.binary(|l, r| l ^ r, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
+                OpCodes::ShiftLeft => {
+                    let Ok(res) = self.binary(|l, r| l << r, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
+                OpCodes::ShiftRight => {
+                    let Ok(res) = self.binary(|l, r| l >> r, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
+                OpCodes::BitNot => {
+                    let Ok(res) = self.bit_not(chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(res)
+                }
                 OpCodes::True => self.stack.push(Literal::Bool(true)),
                 OpCodes::False => self.stack.push(Literal::Bool(false)),
                 OpCodes::None => self.stack.push(Literal::None),
@@ -179,12 +291,24 @@ impl Vm {
                     };
                     self.stack.push(res)
                 }
-                OpCodes::Ternary => {
-                    let Ok(res) = self.ternary(chunk) else {
+                OpCodes::Range | OpCodes::RangeInclusive => {
+                    let inclusive = matches!(instruction, OpCodes::RangeInclusive);
+                    let Ok(range) = self.range(inclusive, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                    self.stack.push(range)
+                }
+
+                OpCodes::In => {
+                    let Ok(res) = self.binary(
+                        |value, container| Ok(Literal::Bool(container.contains(&value)?)),
+                        chunk,
+                    ) else {
                         return InterpretResult::RuntimeError;
                     };
                     self.stack.push(res)
                 }
+
                 OpCodes::Print => {
                     if let Some(literal) = self.stack.pop() {
                         println!("{}", literal);
@@ -196,98 +320,898 @@ impl Vm {
                     self.stack.pop();
                 }
 
-                OpCodes::DefGlobal(index) => {
-                    let Ok(_) = self.def_global(*index, chunk) else {
+                OpCodes::DefGlobal(operand) => {
+                    let Ok(_) = self.def_global(operand, chunk) else {
                         return InterpretResult::RuntimeError;
                     };
                 }
 
-                OpCodes::GetGlobal(index) => {
-                    let Ok(_) = self.get_global(*index, chunk) else {
+                OpCodes::GetGlobal(operand) => {
+                    let Ok(_) = self.get_global(operand, chunk) else {
                         return InterpretResult::RuntimeError;
                     };
                 }
 
-                OpCodes::SetGlobal(index) => {
-                    let Ok(_) = self.set_global(*index, chunk) else {
+                OpCodes::SetGlobal(operand) => {
+                    let Ok(_) = self.set_global(operand, chunk) else {
                         return InterpretResult::RuntimeError;
                     };
                 }
 
-                OpCodes::GetLocal(index) => {
-                    let local = &self.stack[*index];
+                OpCodes::GetLocal(operand) => {
+                    let local = &self.stack[self.frame().slots + operand];
                     self.stack.push(local.clone());
                 }
 
-                OpCodes::SetLocal(index) => {
+                OpCodes::SetLocal(operand) => {
                     if let Some(constant) = self.peek(0) {
-                        self.stack[*index] = constant.clone();
+                        let slot = self.frame().slots + operand;
+                        self.stack[slot] = constant.clone();
                     } else {
                         self.try_error_line("could not find value", chunk);
                         return InterpretResult::RuntimeError;
                     }
                 }
 
-                OpCodes::JumpFalse(offset) => {
+                OpCodes::Closure(operand) => {
+                    let Some(Literal::Function(function)) = chunk.constants.get(operand) else {
+                        self.try_error_line("could not get closure function", chunk);
+                        return InterpretResult::RuntimeError;
+                    };
+
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|upvalue| {
+                            if upvalue.is_local {
+                                self.capture_upvalue(self.frame().slots + upvalue.index)
+                            } else {
+                                self.frame().closure.upvalues[upvalue.index].clone()
+                            }
+                        })
+                        .collect();
+                    let mut closure = Closure::new(function.clone(), upvalues);
+                    closure.owner = self.frame().closure.owner.clone();
+                    let closure = self.heap.alloc(closure);
+                    self.stack.push(Literal::Closure(closure));
+                }
+
+                OpCodes::GetUpvalue(operand) => {
+                    let upvalue = self.frame().closure.upvalues[operand].clone();
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+
+                OpCodes::SetUpvalue(operand) => {
+                    let Some(value) = self.peek(0).cloned() else {
+                        self.try_error_line("could not find value", chunk);
+                        return InterpretResult::RuntimeError;
+                    };
+
+                    let upvalue = self.frame().closure.upvalues[operand].clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    };
+                }
+
+                OpCodes::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+
+                OpCodes::Class(operand) => {
+                    let Some(Literal::Variable(name)) = chunk.constants.get(operand) else {
+                        self.try_error_line("could not get class name", chunk);
+                        return InterpretResult::RuntimeError;
+                    };
+                    let class = self.heap.alloc(RefCell::new(Class::new(name.as_str())));
+                    self.stack.push(Literal::Class(class));
+                }
+
+                OpCodes::Method(operand) => {
+                    let Ok(_) = self.define_method(operand, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::Field(operand) | OpCodes::StaticField(operand) => {
+                    let is_static = matches!(instruction, OpCodes::StaticField(_));
+                    let Ok(_) = self.define_field(operand, is_static, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::Public(operand) => {
+                    let Some(Literal::Variable(name)) = chunk.constants.get(operand) else {
+                        self.try_error_line("could not get member name", chunk);
+                        return InterpretResult::RuntimeError;
+                    };
+                    let Some(Literal::Class(class)) = self.peek(0) else {
+                        self.try_error_line("could not get class for member", chunk);
+                        return InterpretResult::RuntimeError;
+                    };
+                    class.borrow_mut().public.insert(name.to_string());
+                }
+
+                OpCodes::Inherit => {
+                    let Ok(_) = self.inherit(chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::GetSuper(operand) => {
+                    let Ok(_) = self.get_super(operand, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::GetProperty(operand) => {
+                    let Ok(_) = self.get_property(operand, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::SetProperty(operand) => {
+                    let Ok(_) = self.set_property(operand, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::BuildList(operand) => {
+                    let items = self.stack.split_off(self.stack.len() - operand);
+                    let list = self.heap.alloc(RefCell::new(items));
+                    self.stack.push(Literal::List(list));
+                }
+
+                OpCodes::Concat(operand) => {
+                    let parts = self.stack.split_off(self.stack.len() - operand);
+                    let string: String = parts.iter().map(|part| part.to_string()).collect();
+                    let string = self.heap.intern(&string);
+                    self.stack.push(Literal::String(string));
+                }
+
+                OpCodes::BuildMap(operand) => {
+                    let Ok(_) = self.build_map(operand, chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::GetIndex => {
+                    let Ok(_) = self.get_index(chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::SetIndex => {
+                    let Ok(_) = self.set_index(chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::GetIter => {
+                    let Ok(_) = self.get_iter(chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+                }
+
+                OpCodes::ForIter(operand) => {
+                    let Ok(next) = self.iter_next(chunk) else {
+                        return InterpretResult::RuntimeError;
+                    };
+
+                    match next {
+                        Some(value) => self.stack.push(value),
+                        None => self.frame_mut().ip += operand,
+                    }
+                }
+
+                OpCodes::JumpFalse(operand) => {
                     let Some(literal) = self.peek(0) else {
                         panic!("not literal");
                     };
 
                     if !literal.truthy() {
-                        self.ip += offset;
+                        self.frame_mut().ip += operand;
                     }
                 }
 
-                OpCodes::Jump(offset) => {
-                    self.ip += offset;
+                OpCodes::Jump(operand) => {
+                    self.frame_mut().ip += operand;
                 }
 
-                OpCodes::Loop(offset) => {
-                    self.ip -= offset;
+                OpCodes::Loop(operand) => {
+                    self.frame_mut().ip -= operand;
                 }
             }
-            self.bump();
         }
     }
 
-    fn def_global(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
-        let Some(value) = self.peek(0) else {
-            self.try_error_line("could not get variable value", chunk);
+    fn call_value(&mut self, arg_count: usize, chunk: &Chunk) -> Result<(), ()> {
+        let Some(callee) = self.peek(arg_count) else {
+            self.try_error_line("could not find callee", chunk);
             return Err(());
         };
 
+        match callee {
+            Literal::Closure(closure) => self.call(closure.clone(), arg_count, chunk),
+            Literal::Native(native) => self.call_native(native.clone(), arg_count, chunk),
+            Literal::BoundMethod(bound) => {
+                let method = bound.method.clone();
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call(method, arg_count, chunk)
+            }
+            Literal::Class(class) => {
+                let class = class.clone();
+                let initializer = class.borrow().find_method("init");
+                let slot = self.stack.len() - arg_count - 1;
+                let instance = self.heap.alloc(RefCell::new(Instance::new(class)));
+                self.stack[slot] = Literal::Instance(instance.clone());
+                self.initialize_fields(&instance, chunk)?;
+
+                if let Some(initializer) = initializer {
+                    return self.call(initializer, arg_count, chunk);
+                }
+                if arg_count != 0 {
+                    self.try_error_line(
+                        format!("expected 0 arguments but got {}", arg_count).as_str(),
+                        chunk,
+                    );
+                    return Err(());
+                }
+                Ok(())
+            }
+            _ => {
+                self.try_error_line(
+                    format!("cannot call type {}", callee.type_name()).as_str(),
+                    chunk,
+                );
+                Err(())
+            }
+        }
+    }
+
+    fn call_native(
+        &mut self,
+        native: Gc<Native>,
+        arg_count: usize,
+        chunk: &Chunk,
+    ) -> Result<(), ()> {
+        if arg_count != native.arity {
+            self.try_error_line(
+                format!(
+                    "`{}` expected {} arguments but got {}",
+                    native.name, native.arity, arg_count
+                )
+                .as_str(),
+                chunk,
+            );
+            return Err(());
+        }
+
+        let args_start = self.stack.len() - arg_count;
+        let result = (native.function)(&self.stack[args_start..]);
+        match result {
+            Ok(value) => {
+                // drop the arguments and the callee
+                self.stack.truncate(args_start - 1);
+                self.stack.push(value);
+                Ok(())
+            }
+            Err(err) => {
+                self.try_error_line(err.as_str(), chunk);
+                Err(())
+            }
+        }
+    }
+
+    fn call(&mut self, closure: Gc<Closure>, arg_count: usize, chunk: &Chunk) -> Result<(), ()> {
+        let function = &closure.function;
+        if arg_count != function.arity as usize {
+            self.try_error_line(
+                format!(
+                    "`{}` expected {} arguments but got {}",
+                    function.name, function.arity, arg_count
+                )
+                .as_str(),
+                chunk,
+            );
+            return Err(());
+        }
+
+        if self.frames.len() == FRAMES_MAX {
+            self.try_error_line("stack overflow", chunk);
+            return Err(());
+        }
+
+        let slots = self.stack.len() - arg_count - 1;
+        self.frames.push(CallFrame::new(closure, slots));
+        Ok(())
+    }
+
+    fn define_method(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
         let Some(Literal::Variable(name)) = chunk.constants.get(index) else {
-            self.try_error_line("could not get variable name", chunk);
+            self.try_error_line("could not get method name", chunk);
             return Err(());
         };
 
-        self.globals.insert(name.clone(), value.clone());
-        self.stack.pop();
+        let Some(Literal::Closure(method)) = self.stack.pop() else {
+            self.try_error_line("could not get method body", chunk);
+            return Err(());
+        };
+
+        let Some(Literal::Class(class)) = self.peek(0).cloned() else {
+            self.try_error_line("could not get class for method", chunk);
+            return Err(());
+        };
+
+        let method = self.heap.alloc(method.with_owner(class.clone()));
+        class.borrow_mut().methods.insert(name.to_string(), method);
         Ok(())
     }
 
-    fn get_global(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
+    fn define_field(&mut self, index: usize, is_static: bool, chunk: &Chunk) -> Result<(), ()> {
         let Some(Literal::Variable(name)) = chunk.constants.get(index) else {
-            self.try_error_line("could not get variable name", chunk);
+            self.try_error_line("could not get field name", chunk);
             return Err(());
         };
 
-        let Some(value) = self.globals.get(name) else {
-            self.try_error_line(format!("undefined variable '{}'", name).as_str(), chunk);
+        let Some(value) = self.stack.pop() else {
+            self.try_error_line(
+                format!("could not get value for field '{}'", name).as_str(),
+                chunk,
+            );
             return Err(());
         };
 
-        self.stack.push(value.clone());
+        let Some(Literal::Class(class)) = self.peek(0).cloned() else {
+            self.try_error_line("could not get class for field", chunk);
+            return Err(());
+        };
+
+        if !is_static {
+            let Literal::Closure(initializer) = value else {
+                self.try_error_line(
+                    format!("could not get initializer for field '{}'", name).as_str(),
+                    chunk,
+                );
+                return Err(());
+            };
+            // initializers may read private members through `this`
+            let initializer = self.heap.alloc(initializer.with_owner(class.clone()));
+            class
+                .borrow_mut()
+                .fields
+                .push((name.to_string(), initializer));
+            return Ok(());
+        }
+
+        // static methods still get access to the class's private members
+        let value = match value {
+            Literal::Closure(method) => {
+                Literal::Closure(self.heap.alloc(method.with_owner(class.clone())))
+            }
+            value => value,
+        };
+        class.borrow_mut().statics.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn check_access(
+        &self,
+        class: &Gc<RefCell<Class>>,
+        name: &str,
+        chunk: &Chunk,
+    ) -> Result<(), ()> {
+        if class.borrow().is_public(name) {
+            return Ok(());
+        }
+
+        // private members are only visible to the methods of the class declaring them, not to
+        // those of its subclasses
+        let declaring = Class::declaring(class, name);
+        if let Some(owner) = &self.frame().closure.owner {
+            if Gc::ptr_eq(&declaring, owner) {
+                return Ok(());
+            }
+        }
+
+        self.try_error_line(
+            format!(
+                "cannot access private member '{}' of class {}",
+                name,
+                declaring.borrow().name
+            )
+            .as_str(),
+            chunk,
+        );
+        Err(())
+    }
+
+    // a field no class declares belongs to the class whose method assigns it first
+    fn record_assigned(&self, class: &Gc<RefCell<Class>>, name: &str) {
+        let Some(owner) = &self.frame().closure.owner else {
+            return;
+        };
+        let declared = Class::declaring(class, name).borrow().declares(name);
+        if !declared && Class::inherits(class, owner) {
+            owner.borrow_mut().assigned.insert(name.to_string());
+        }
+    }
+
+    fn inherit(&mut self, chunk: &Chunk) -> Result<(), ()> {
+        let Some(Literal::Class(class)) = self.stack.pop() else {
+            self.try_error_line("could not get class to inherit into", chunk);
+            return Err(());
+        };
+
+        let Some(Literal::Class(superclass)) = self.peek(0) else {
+            self.try_error_line("superclass must be a class", chunk);
+            return Err(());
+        };
+
+        class.borrow_mut().superclass = Some(superclass.clone());
         Ok(())
     }
 
-    fn set_global(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
+    fn get_super(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
         let Some(Literal::Variable(name)) = chunk.constants.get(index) else {
-            self.try_error_line("could not get variable name", chunk);
+            self.try_error_line("could not get method name", chunk);
             return Err(());
         };
 
-        if !self.globals.contains_key(name) {
+        let Some(Literal::Class(superclass)) = self.stack.pop() else {
+            self.try_error_line("could not get superclass", chunk);
+            return Err(());
+        };
+
+        let Some(receiver) = self.stack.pop() else {
+            self.try_error_line("could not get receiver for `super`", chunk);
+            return Err(());
+        };
+
+        let Some(method) = superclass.borrow().find_method(name) else {
+            self.try_error_line(
+                format!("undefined superclass method '{}'", name).as_str(),
+                chunk,
+            );
+            return Err(());
+        };
+
+        let bound = self.heap.alloc(BoundMethod::new(receiver, method));
+        self.stack.push(Literal::BoundMethod(bound));
+        Ok(())
+    }
+
+    fn get_property(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
+        let Some(Literal::Variable(name)) = chunk.constants.get(index) else {
+            self.try_error_line("could not get property name", chunk);
+            return Err(());
+        };
+
+        let value = match self.peek(0).cloned() {
+            Some(Literal::Instance(instance)) => {
+                let class = instance.borrow().class.clone();
+                let field = instance.borrow().fields.get(name.as_str()).cloned();
+                let value = match field {
+                    Some(value) => Some(value),
+                    None => class.borrow().find_method(name).map(|method| {
+                        let bound = BoundMethod::new(Literal::Instance(instance), method);
+                        Literal::BoundMethod(self.heap.alloc(bound))
+                    }),
+                };
+                value.map(|value| (class, value))
+            }
+            Some(Literal::Class(class)) => {
+                let value = class.borrow().find_static(name);
+                value.map(|value| (class, value))
+            }
+            value => {
+                let type_name = value.map(|value| value.type_name()).unwrap_or_default();
+                self.try_error_line(
+                    format!("cannot access property '{}' on type {}", name, type_name).as_str(),
+                    chunk,
+                );
+                return Err(());
+            }
+        };
+
+        let Some((class, value)) = value else {
+            self.try_error_line(format!("undefined property '{}'", name).as_str(), chunk);
+            return Err(());
+        };
+
+        self.check_access(&class, name, chunk)?;
+        self.stack.pop();
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn set_property(&mut self, index: usize, chunk: &Chunk) -> Result<(), ()> {
+        let Some(Literal::Variable(name)) = chunk.constants.get(index) else {
+            self.try_error_line("could not get property name", chunk);
+            return Err(());
+        };
+
+        let Some(value) = self.stack.pop() else {
+            self.try_error_line(
+                format!("could not get assignment value for '{}'", name).as_str(),
+                chunk,
+            );
+            return Err(());
+        };
+
+        match self.stack.pop() {
+            Some(Literal::Instance(instance)) => {
+                let class = instance.borrow().class.clone();
+                self.record_assigned(&class, name);
+                self.check_access(&class, name, chunk)?;
+                instance
+                    .borrow_mut()
+                    .fields
+                    .insert(name.to_string(), value.clone());
+            }
+            Some(Literal::Class(class)) => {
+                self.check_access(&class, name, chunk)?;
+                class.borrow_mut().set_static(name, value.clone());
+            }
+            _ => {
+                self.try_error_line(
+                    format!("cannot set property '{}' on non-instance", name).as_str(),
+                    chunk,
+                );
+                return Err(());
+            }
+        }
+
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn build_map(&mut self, count: usize, chunk: &Chunk) -> Result<(), ()> {
+        let entries = self.stack.split_off(self.stack.len() - count * 2);
+        let mut map = Map::new();
+        for entry in entries.chunks(2) {
+            if let Err(err) = map.insert(entry[0].clone(), entry[1].clone()) {
+                self.try_error_line(err.as_str(), chunk);
+                return Err(());
+            }
+        }
+        let map = self.heap.alloc(RefCell::new(map));
+        self.stack.push(Literal::Map(map));
+        Ok(())
+    }
+
+    fn get_index(&mut self, chunk: &Chunk) -> Result<(), ()> {
+        let Some(index) = self.stack.pop() else {
+            self.try_error_line("no index to get", chunk);
+            return Err(());
+        };
+        let Some(value) = self.stack.pop() else {
+            self.try_error_line("no value to index", chunk);
+            return Err(());
+        };
+
+        let item = match value {
+            Literal::List(list) => {
+                let position = self.list_position(&list.borrow(), &index, chunk)?;
+                let item = list.borrow()[position].clone();
+                item
+            }
+            Literal::Map(map) => {
+                let result = map.borrow().get(&index);
+                match result {
+                    Ok(Some(item)) => item,
+                    Ok(None) => {
+                        self.try_error_line(
+                            format!("key {} not found in map", index).as_str(),
+                            chunk,
+                        );
+                        return Err(());
+                    }
+                    Err(err) => {
+                        self.try_error_line(err.as_str(), chunk);
+                        return Err(());
+                    }
+                }
+            }
+            value => {
+                self.try_error_line(
+                    format!("type {} is not indexable", value.type_name()).as_str(),
+                    chunk,
+                );
+                return Err(());
+            }
+        };
+        self.stack.push(item);
+        Ok(())
+    }
+
+    fn set_index(&mut self, chunk: &Chunk) -> Result<(), ()> {
+        let Some(item) = self.stack.pop() else {
+            self.try_error_line("no value to assign", chunk);
+            return Err(());
+        };
+        let Some(index) = self.stack.pop() else {
+            self.try_error_line("no index to set", chunk);
+            return Err(());
+        };
+        let Some(value) = self.stack.pop() else {
+            self.try_error_line("no value to index", chunk);
+            return Err(());
+        };
+
+        match value {
+            Literal::List(list) => {
+                let position = self.list_position(&list.borrow(), &index, chunk)?;
+                list.borrow_mut()[position] = item.clone();
+            }
+            Literal::Map(map) => {
+                let result = map.borrow_mut().insert(index, item.clone());
+                if let Err(err) = result {
+                    self.try_error_line(err.as_str(), chunk);
+                    return Err(());
+                }
+            }
+            value => {
+                self.try_error_line(
+                    format!(
+                        "type {} does not support index assignment",
+                        value.type_name()
+                    )
+                    .as_str(),
+                    chunk,
+                );
+                return Err(());
+            }
+        }
+        self.stack.push(item);
+        Ok(())
+    }
+
+    // negative indices count back from the end of the list
+    fn list_position(&self, list: &[Literal], index: &Literal, chunk: &Chunk) -> Result<usize, ()> {
+        let Literal::Int(index) = *index else {
+            self.try_error_line(
+                format!("list index must be int, got {}", index.type_name()).as_str(),
+                chunk,
+            );
+            return Err(());
+        };
+
+        let position = if index < 0 {
+            index + list.len() as isize
+        } else {
+            index
+        };
+        if position < 0 || position as usize >= list.len() {
+            self.try_error_line(
+                format!(
+                    "index {} out of bounds for list of length {}",
+                    index,
+                    list.len()
+                )
+                .as_str(),
+                chunk,
+            );
+            return Err(());
+        }
+        Ok(position as usize)
+    }
+
+    fn get_iter(&mut self, chunk: &Chunk) -> Result<(), ()> {
+        let Some(value) = self.stack.pop() else {
+            self.try_error_line("no value to iterate over", chunk);
+            return Err(());
+        };
+
+        let iter = match value {
+            Literal::Iterator(iter) => iter,
+            value => {
+                let iter = self.make_iter(value, chunk)?;
+                self.heap.alloc(RefCell::new(iter))
+            }
+        };
+        self.stack.push(Literal::Iterator(iter));
+        Ok(())
+    }
+
+    fn make_iter(&mut self, value: Literal, chunk: &Chunk) -> Result<Iter, ()> {
+        match value {
+            Literal::Int(end) => Ok(Iter::Range {
+                range: Range::new(0, end, 1, false),
+                next: Some(0),
+            }),
+            Literal::Range(range) => Ok(Iter::Range {
+                range,
+                next: Some(range.start),
+            }),
+            Literal::String(string) => Ok(Iter::Chars {
+                chars: string.chars().collect(),
+                index: 0,
+            }),
+            Literal::List(list) => Ok(Iter::List { list, index: 0 }),
+            // iterate over a snapshot of the keys so the map can be changed in the loop
+            Literal::Map(map) => Ok(Iter::List {
+                list: self.heap.alloc(RefCell::new(map.borrow().keys())),
+                index: 0,
+            }),
+            Literal::Instance(instance) => {
+                let class = instance.borrow().class.clone();
+                let has_method = |name: &str| class.borrow().find_method(name).is_some();
+                if has_method("has_next") && has_method("next") {
+                    return Ok(Iter::Object(Literal::Instance(instance)));
+                }
+
+                if !has_method("iter") {
+                    self.try_error_line(
+                        format!("instance of {} is not iterable", class.borrow().name).as_str(),
+                        chunk,
+                    );
+                    return Err(());
+                }
+
+                match self.call_method(Literal::Instance(instance), "iter", chunk)? {
+                    Literal::Instance(iterator) => {
+                        let class = iterator.borrow().class.clone();
+                        let has_method = |name: &str| class.borrow().find_method(name).is_some();
+                        if !has_method("has_next") || !has_method("next") {
+                            self.try_error_line(
+                                format!(
+                                    "`iter` returned an instance of {} without `has_next` and `next`",
+                                    class.borrow().name
+                                )
+                                .as_str(),
+                                chunk,
+                            );
+                            return Err(());
+                        }
+                        Ok(Iter::Object(Literal::Instance(iterator)))
+                    }
+                    value => self.make_iter(value, chunk),
+                }
+            }
+            value => {
+                self.try_error_line(
+                    format!("type {} is not iterable", value.type_name()).as_str(),
+                    chunk,
+                );
+                Err(())
+            }
+        }
+    }
+
+    fn iter_next(&mut self, chunk: &Chunk) -> Result<Option<Literal>, ()> {
+        let Some(Literal::Iterator(iter)) = self.peek(0).cloned() else {
+            self.try_error_line("could not get loop iterator", chunk);
+            return Err(());
+        };
+
+        let object = match &*iter.borrow() {
+            Iter::Object(object) => Some(object.clone()),
+            _ => None,
+        };
+        let Some(object) = object else {
+            return Ok(iter.borrow_mut().next(&mut self.heap));
+        };
+
+        if !self
+            .call_method(object.clone(), "has_next", chunk)?
+            .truthy()
+        {
+            return Ok(None);
+        }
+        Ok(Some(self.call_method(object, "next", chunk)?))
+    }
+
+    // runs every field initializer with the new instance as `this`, before `init` is called
+    fn initialize_fields(
+        &mut self,
+        instance: &Gc<RefCell<Instance>>,
+        chunk: &Chunk,
+    ) -> Result<(), ()> {
+        let initializers = instance.borrow().class.borrow().field_initializers();
+        for (name, initializer) in initializers {
+            let depth = self.frames.len();
+            self.stack.push(Literal::Instance(instance.clone()));
+            self.call(initializer, 0, chunk)?;
+            if self.run(depth) != InterpretResult::Ok {
+                return Err(());
+            }
+            let value = self.stack.pop().unwrap_or(Literal::None);
+            instance.borrow_mut().fields.insert(name, value);
+        }
+        Ok(())
+    }
+
+    // calls a method with no arguments and runs it to completion
+    fn call_method(&mut self, receiver: Literal, name: &str, chunk: &Chunk) -> Result<Literal, ()> {
+        let Literal::Instance(instance) = &receiver else {
+            self.try_error_line(
+                format!(
+                    "cannot call method '{}' on type {}",
+                    name,
+                    receiver.type_name()
+                )
+                .as_str(),
+                chunk,
+            );
+            return Err(());
+        };
+
+        let Some(method) = instance.borrow().class.borrow().find_method(name) else {
+            self.try_error_line(format!("undefined method '{}'", name).as_str(), chunk);
+            return Err(());
+        };
+
+        let depth = self.frames.len();
+        self.stack.push(receiver.clone());
+        self.call(method, 0, chunk)?;
+        if self.run(depth) != InterpretResult::Ok {
+            return Err(());
+        }
+        Ok(self.stack.pop().unwrap_or(Literal::None))
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Gc<RefCell<Upvalue>> {
+        let open = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(&*upvalue.borrow(), Upvalue::Open(open) if *open == slot));
+        if let Some(upvalue) = open {
+            return upvalue.clone();
+        }
+
+        let upvalue = self.heap.alloc(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    // closes every open upvalue pointing at `last` or above
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            let Upvalue::Open(slot) = *upvalue else {
+                return false;
+            };
+            if slot < last {
+                return true;
+            }
+
+            *upvalue = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+
+    fn def_global(&mut self, slot: usize, chunk: &Chunk) -> Result<(), ()> {
+        let Some(value) = self.stack.pop() else {
+            self.try_error_line("could not get variable value", chunk);
+            return Err(());
+        };
+
+        self.globals[slot] = Some(value);
+        Ok(())
+    }
+
+    fn get_global(&mut self, slot: usize, chunk: &Chunk) -> Result<(), ()> {
+        let Some(value) = &self.globals[slot] else {
+            let name = self.global_names.name(slot);
+            self.try_error_line(format!("undefined variable '{}'", name).as_str(), chunk);
+            return Err(());
+        };
+
+        self.stack.push(value.clone());
+        Ok(())
+    }
+
+    fn set_global(&mut self, slot: usize, chunk: &Chunk) -> Result<(), ()> {
+        let name = self.global_names.name(slot);
+        if self.globals[slot].is_none() {
             self.try_error_line(format!("undefined variable '{}'", name).as_str(), chunk);
             return Err(());
         }
@@ -299,7 +1223,7 @@ impl Vm {
             return Err(());
         };
 
-        self.globals.insert(name.to_string(), value.clone());
+        self.globals[slot] = Some(value.clone());
         Ok(())
     }
 
@@ -315,7 +1239,18 @@ impl Vm {
             );
             return Err(());
         }
-        return Ok(c.negate());
+        c.negate()
+            .map_err(|err| self.try_error_line(err.as_str(), chunk))
+    }
+
+    fn bit_not(&mut self, chunk: &Chunk) -> Result<Literal, ()> {
+        let Some(c) = self.stack.pop() else {
+            self.try_error_line("no operand for `~` expression", chunk);
+            return Err(());
+        };
+
+        c.bit_not()
+            .map_err(|err| self.try_error_line(err.as_str(), chunk))
     }
 
     fn not(&mut self, chunk: &Chunk) -> Result<Literal, ()> {
@@ -355,38 +1290,103 @@ impl Vm {
         };
     }
 
-    fn ternary(&mut self, chunk: &Chunk) -> Result<Literal, ()> {
-        let Some(falsey) = self.stack.pop() else {
-            self.try_error_line(
-                format!("no `falsey` expression for ternary operator").as_str(),
-                chunk,
-            );
-            return Err(());
-        };
+    // strings are interned on the heap, so concatenation can't go through `Literal`'s `Add`
+    fn add(&mut self, chunk: &Chunk) -> Result<Literal, ()> {
+        if let (Some(Literal::String(a)), Some(Literal::String(b))) = (self.peek(1), self.peek(0)) {
+            let string = format!("{}{}", **a, **b);
+            self.stack.truncate(self.stack.len() - 2);
+            return Ok(Literal::String(self.heap.intern(&string)));
+        }
+        self.binary(|l, r| l + r, chunk)
+    }
 
-        let Some(truthy) = self.stack.pop() else {
-            self.try_error_line(
-                format!("no `truthy` expression for ternary operator").as_str(),
-                chunk,
-            );
-            return Err(());
-        };
+    fn multiply(&mut self, chunk: &Chunk) -> Result<Literal, ()> {
+        if let (Some(Literal::String(a)), Some(Literal::Int(b))) = (self.peek(1), self.peek(0)) {
+            let Ok(count) = usize::try_from(*b) else {
+                self.try_error_line(
+                    format!("cannot repeat a string {} times", b).as_str(),
+                    chunk,
+                );
+                return Err(());
+            };
+            // `str::repeat` panics once the length no longer fits in an `isize`
+            let fits = a
+                .len()
+                .checked_mul(count)
+                .is_some_and(|length| isize::try_from(length).is_ok());
+            if !fits {
+                self.try_error_line(
+                    format!("string repeated {} times is too long", count).as_str(),
+                    chunk,
+                );
+                return Err(());
+            }
+            let string = a.repeat(count);
+            self.stack.truncate(self.stack.len() - 2);
+            return Ok(Literal::String(self.heap.intern(&string)));
+        }
+        self.binary(|l, r| l * r, chunk)
+    }
+
+    fn range(&mut self, inclusive: bool, chunk: &Chunk) -> Result<Literal, ()> {
+        // operands are pushed as start, end, step
+        let mut operands = [0; 3];
+        for operand in operands.iter_mut().rev() {
+            match self.stack.pop() {
+                Some(Literal::Int(value)) => *operand = value,
+                Some(value) => {
+                    self.try_error_line(
+                        format!("range bounds must be int, got {}", value.type_name()).as_str(),
+                        chunk,
+                    );
+                    return Err(());
+                }
+                None => {
+                    self.try_error_line("missing operand for range", chunk);
+                    return Err(());
+                }
+            }
+        }
 
-        let Some(condition) = self.stack.pop() else {
-            self.try_error_line(format!("no condition for ternary operator").as_str(), chunk);
+        let [start, end, step] = operands;
+        if step == 0 {
+            self.try_error_line("range step cannot be zero", chunk);
             return Err(());
-        };
+        }
+        Ok(Literal::Range(Range::new(start, end, step, inclusive)))
+    }
 
-        if let Literal::Bool(v) = condition {
-            if v {
-                return Ok(truthy);
-            }
+    // roots are the stack, globals, active closures and open upvalues, constants are
+    // reached through the functions that own them
+    fn collect_garbage(&mut self) {
+        let mut tracer = Tracer::new();
+        for value in self.stack.iter().chain(self.globals.iter().flatten()) {
+            value.trace(&mut tracer);
+        }
+        self.global_names.trace(&mut tracer);
+        for frame in self.frames.iter() {
+            tracer.mark(&frame.closure);
+        }
+        for upvalue in self.open_upvalues.iter() {
+            tracer.mark(upvalue);
         }
-        Ok(falsey)
+        self.heap.collect(tracer);
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
     }
 
     fn bump(&mut self) {
-        self.ip += 1
+        self.frame_mut().ip += 1
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("no active call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("no active call frame")
     }
 
     fn peek(&self, distance: usize) -> Option<&Literal> {
@@ -405,34 +1405,33 @@ impl Vm {
     }
 
     fn reset_stack(&mut self) {
-        self.ip = 0;
+        self.frames.clear();
+        self.open_upvalues.clear();
         self.stack.clear()
     }
 
+    // reports the error at the failing instruction in `chunk`, followed by every active call
     fn try_error_line(&self, message: &str, chunk: &Chunk) {
-        if let Some(line) = chunk.get_line(self.ip) {
-            if let Some(span) = self.get_source(line.line) {
-                error_at!(&span, "{}", message);
-                return;
-            }
-            let line = line.line;
-            error_line!(&self.create_span(line), "{}", message)
-        } else {
-            // probably unreachable
-            error!("{}", message)
+        let mut trace = Vec::new();
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            let function = &frame.closure.function;
+            let chunk = if depth == 0 { chunk } else { &function.chunk };
+            // the instruction pointer has already moved past the failing instruction or call
+            let Some(line) = chunk.get_line(frame.ip - 1) else {
+                continue;
+            };
+            trace.push((
+                function.name.as_str(),
+                Span::new(function.file.clone(), line.location),
+            ));
         }
-    }
 
-    fn create_span(&self, line: u32) -> Span {
-        let file = self.options.file_path.to_str().unwrap();
-        Span::new(file.into(), Location::new(line, 0, 0))
-    }
-
-    pub fn get_source(&self, line: u32) -> Option<Span> {
-        let binding = self.source_map.borrow();
-        let Some(span) = binding.iter().find(|&map| map.location.line == line) else {
-            return None;
+        let Some((_, span)) = trace.first() else {
+            // probably unreachable
+            error!("{}", message);
+            return;
         };
-        Some(span.dup())
+        error_at!(span, "{}", message);
+        Log::stack_trace(Level::Error, &trace);
     }
 }
@@ -1,10 +1,20 @@
-use crate::frontend::interpretation::{literal::Literal, op_codes::OpCodes};
+use std::collections::HashMap;
 
+use crate::{
+    common::gc::Gc,
+    frontend::{
+        interpretation::{literal::Literal, op_codes::OpCodes},
+        tokenization::location::Location,
+    },
+};
+
+// source location of a run of instructions emitted for the same token
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Line {
-    pub line: u32,
+    pub location: Location,
     start: usize,
-    end: Option<usize>,
+    // one past the last instruction belonging to this run
+    end: usize,
 }
 
 #[derive(Debug)]
@@ -12,6 +22,8 @@ pub struct Chunk {
     pub code: Vec<OpCodes>,
     pub constants: Vec<Literal>,
     pub lines: Vec<Line>,
+    // constant index of every identifier already in the pool
+    names: HashMap<Gc<String>, usize>,
 }
 
 impl Chunk {
@@ -20,36 +32,49 @@ impl Chunk {
             code: Vec::new(),
             constants: Vec::new(),
             lines: Vec::new(),
+            names: HashMap::new(),
         }
     }
 
-    pub fn write(&mut self, op_code: OpCodes, line: u32) {
+    pub fn write(&mut self, op_code: OpCodes, location: Location) {
         self.code.push(op_code);
-        if let Some(index) = self.lines.last() {
-            if line > index.line {
-                self.lines.push(Line {
-                    line,
-                    start: self.code.len() - 1,
-                    end: None,
-                })
-            }
-            let new_len = self.lines.len();
-
-            if let Some(prev) = self.lines.get_mut(new_len - 1) {
-                prev.end = Some(self.code.len() - 1);
-            }
-        } else {
-            self.lines.push(Line {
-                line,
+        match self.lines.last_mut() {
+            Some(last) if location == last.location => last.end = self.code.len(),
+            _ => self.lines.push(Line {
+                location,
                 start: self.code.len() - 1,
-                end: None,
-            })
+                end: self.code.len(),
+            }),
         }
     }
 
-    pub fn add_constant(&mut self, constant: Literal, line: u32) {
+    // returns the offset of the jump so it can be patched once its target is known
+    pub fn write_jump(&mut self, op_code: OpCodes, location: Location) -> usize {
+        let offset = self.code.len();
+        self.write(op_code, location);
+        offset
+    }
+
+    // points the forward jump at `offset` to the end of the code
+    pub fn patch_jump(&mut self, offset: usize) {
+        let jump = self.code.len() - offset - 1;
+        self.code[offset] = self.code[offset].patch_jump(jump);
+    }
+
+    // the loop instruction itself counts towards the distance back to `start`
+    pub fn write_loop(&mut self, start: usize, location: Location) {
+        let jump = self.code.len() + 1 - start;
+        self.write(OpCodes::Loop(jump), location)
+    }
+
+    // returns the instruction at `offset` with the offset of the next one
+    pub fn read(&self, offset: usize) -> (OpCodes, usize) {
+        (self.code[offset].clone(), offset + 1)
+    }
+
+    pub fn add_constant(&mut self, constant: Literal, location: Location) {
         self.constants.push(constant);
-        self.write(OpCodes::Constant(self.constants.len() - 1), line);
+        self.write(OpCodes::Constant(self.constants.len() - 1), location);
     }
 
     pub fn add_constant_manual(&mut self, constant: Literal) -> usize {
@@ -57,13 +82,20 @@ impl Chunk {
         self.constants.len() - 1
     }
 
+    // identifiers are interned, so repeated uses of a name share one constant
+    pub fn add_identifier(&mut self, name: Gc<String>) -> usize {
+        if let Some(index) = self.names.get(&name) {
+            return *index;
+        }
+        let index = self.add_constant_manual(Literal::Variable(name.clone()));
+        self.names.insert(name, index);
+        index
+    }
+
     pub fn get_line(&self, index: usize) -> Option<&Line> {
-        self.lines.iter().find(|&line| {
-            if let Some(end) = line.end {
-                return index >= line.start && index < end;
-            }
-            index >= line.start
-        })
+        self.lines
+            .iter()
+            .find(|&line| index >= line.start && index < line.end)
     }
 
     pub fn check_previous(&self, offset: usize) -> bool {
@@ -75,10 +107,77 @@ impl Chunk {
             return false;
         };
 
-        if current.line == previous.line {
+        if current.location.line == previous.location.line {
             return true;
         }
 
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Chunk;
+    use crate::frontend::{interpretation::op_codes::OpCodes, tokenization::location::Location};
+
+    // the largest 0, 8, 16 and 24 bit values and the first ones past them
+    const OPERANDS: [usize; 7] = [0, 0xFF, 0x100, 0xFFFF, 0x1_0000, 0xFF_FFFF, 0x100_0000];
+
+    // offset the vm resumes at after running the jump at `offset`
+    fn jump_target(chunk: &Chunk, offset: usize) -> usize {
+        match chunk.read(offset) {
+            (OpCodes::Jump(jump) | OpCodes::JumpFalse(jump), next) => next + jump,
+            (OpCodes::Loop(jump), next) => next - jump,
+            (op_code, _) => panic!("{op_code} is not a jump"),
+        }
+    }
+
+    #[test]
+    fn operands_survive_write_and_read() {
+        let mut chunk = Chunk::new();
+        for operand in OPERANDS {
+            chunk.write(OpCodes::GetLocal(operand), Location::default());
+            chunk.write(OpCodes::Pop, Location::default());
+        }
+
+        let mut offset = 0;
+        for operand in OPERANDS {
+            let (op_code, next) = chunk.read(offset);
+            assert!(matches!(op_code, OpCodes::GetLocal(read) if read == operand));
+            let (op_code, next) = chunk.read(next);
+            assert!(matches!(op_code, OpCodes::Pop));
+            offset = next;
+        }
+        assert_eq!(offset, chunk.code.len());
+    }
+
+    #[test]
+    fn patched_jumps_land_after_the_skipped_code() {
+        for skipped in [0, 0xFF, 0x100, 0xFFFF, 0x1_0000] {
+            let mut chunk = Chunk::new();
+            let jump = chunk.write_jump(OpCodes::JumpFalse(0), Location::default());
+            for _ in 0..skipped {
+                chunk.write(OpCodes::Pop, Location::default());
+            }
+            chunk.patch_jump(jump);
+
+            assert!(matches!(chunk.code[jump], OpCodes::JumpFalse(operand) if operand == skipped));
+            assert_eq!(jump_target(&chunk, jump), chunk.code.len());
+        }
+    }
+
+    #[test]
+    fn loops_land_on_their_start() {
+        for body in [0, 0xFE, 0xFF, 0xFFFE, 0xFFFF] {
+            let mut chunk = Chunk::new();
+            chunk.write(OpCodes::None, Location::default());
+            let start = chunk.code.len();
+            for _ in 0..body {
+                chunk.write(OpCodes::Pop, Location::default());
+            }
+            chunk.write_loop(start, Location::default());
+
+            assert_eq!(jump_target(&chunk, chunk.code.len() - 1), start);
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    borrow::Borrow,
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    mem,
+    ops::Deref,
+    ptr::NonNull,
+};
+
+const INITIAL_THRESHOLD: usize = 1024 * 1024;
+const GROW_FACTOR: usize = 2;
+
+// implemented by everything that can live on the heap
+pub trait Trace {
+    // marks every handle reachable from this value
+    fn trace(&self, tracer: &mut Tracer);
+
+    // bytes owned by the value outside of its own allocation
+    fn extra_size(&self) -> usize {
+        0
+    }
+}
+
+struct GcBox<T: ?Sized> {
+    marked: Cell<bool>,
+    // bytes accounted to this object when it was allocated
+    size: usize,
+    value: T,
+}
+
+// handle to an object owned by the `Heap`, only valid while the object is reachable from a root
+pub struct Gc<T> {
+    ptr: NonNull<GcBox<T>>,
+}
+
+impl<T> Gc<T> {
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptr == b.ptr
+    }
+
+    fn is_marked(&self) -> bool {
+        unsafe { self.ptr.as_ref().marked.get() }
+    }
+}
+
+// handles compare by identity, which for interned strings is also equality of contents
+impl<T> PartialEq for Gc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Self::ptr_eq(self, other)
+    }
+}
+
+impl<T> Eq for Gc<T> {}
+
+impl<T> Hash for Gc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ptr.hash(state)
+    }
+}
+
+impl<T> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        Self { ptr: self.ptr }
+    }
+}
+
+impl<T> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // the heap only frees objects that no root can reach
+        unsafe { &self.ptr.as_ref().value }
+    }
+}
+
+impl<T> Debug for Gc<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Gc({:p})", self.ptr)
+    }
+}
+
+impl<T: Display> Display for Gc<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", **self)
+    }
+}
+
+pub struct Tracer {
+    gray: Vec<NonNull<GcBox<dyn Trace>>>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self { gray: Vec::new() }
+    }
+
+    pub fn mark<T: Trace + 'static>(&mut self, gc: &Gc<T>) {
+        let header = unsafe { gc.ptr.as_ref() };
+        if header.marked.replace(true) {
+            return;
+        }
+        self.gray.push(gc.ptr);
+    }
+}
+
+// entry of the intern table, hashed by contents so it can be looked up by `&str`
+struct Interned(Gc<String>);
+
+impl Borrow<str> for Interned {
+    fn borrow(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for Interned {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Interned {}
+
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_str().hash(state)
+    }
+}
+
+pub struct Heap {
+    objects: Vec<NonNull<GcBox<dyn Trace>>>,
+    // does not keep its strings alive, unreachable ones are dropped on collection
+    strings: HashSet<Interned>,
+    bytes_allocated: usize,
+    total_allocated: usize,
+    next_gc: usize,
+    collections: usize,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+            strings: HashSet::new(),
+            bytes_allocated: 0,
+            total_allocated: 0,
+            next_gc: INITIAL_THRESHOLD,
+            collections: 0,
+        }
+    }
+
+    // never collects, the vm decides when it is safe to do so
+    pub fn alloc<T: Trace + 'static>(&mut self, value: T) -> Gc<T> {
+        let size = mem::size_of::<GcBox<T>>() + value.extra_size();
+        let boxed = Box::new(GcBox {
+            marked: Cell::new(false),
+            size,
+            value,
+        });
+        let ptr = NonNull::from(Box::leak(boxed));
+        self.objects.push(ptr);
+        self.bytes_allocated += size;
+        self.total_allocated += size;
+        Gc { ptr }
+    }
+
+    // returns the one shared string with these contents
+    pub fn intern(&mut self, string: &str) -> Gc<String> {
+        if let Some(interned) = self.strings.get(string) {
+            return interned.0.clone();
+        }
+        let interned = self.alloc(string.to_string());
+        self.strings.insert(Interned(interned.clone()));
+        interned
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.bytes_allocated > self.next_gc
+    }
+
+    // `tracer` must already have every root marked
+    pub fn collect(&mut self, mut tracer: Tracer) {
+        while let Some(ptr) = tracer.gray.pop() {
+            unsafe { ptr.as_ref().value.trace(&mut tracer) };
+        }
+        self.strings.retain(|interned| interned.0.is_marked());
+        self.sweep();
+        self.collections += 1;
+        self.next_gc = (self.bytes_allocated * GROW_FACTOR).max(INITIAL_THRESHOLD);
+    }
+
+    fn sweep(&mut self) {
+        let mut freed = 0;
+        self.objects.retain(|ptr| {
+            let header = unsafe { ptr.as_ref() };
+            if header.marked.replace(false) {
+                return true;
+            }
+            freed += header.size;
+            unsafe { drop(Box::from_raw(ptr.as_ptr())) };
+            false
+        });
+        self.bytes_allocated -= freed;
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        for ptr in self.objects.drain(..) {
+            unsafe { drop(Box::from_raw(ptr.as_ptr())) };
+        }
+    }
+}
+
+impl Display for Heap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[gc] collections: {}, allocated: {} bytes, live: {} bytes in {} objects, interned strings: {}",
+            self.collections,
+            self.total_allocated,
+            self.bytes_allocated,
+            self.objects.len(),
+            self.strings.len()
+        )
+    }
+}
+
+impl<T: Trace> Trace for RefCell<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.borrow().trace(tracer);
+    }
+
+    fn extra_size(&self) -> usize {
+        self.borrow().extra_size()
+    }
+}
+
+impl Trace for String {
+    fn trace(&self, _tracer: &mut Tracer) {}
+
+    fn extra_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use super::{Gc, Heap, Trace, Tracer};
+
+    // counts its drops so tests can tell which objects a sweep freed
+    struct Node {
+        next: Option<Gc<RefCell<Node>>>,
+        drops: Rc<Cell<usize>>,
+    }
+
+    impl Node {
+        fn new(drops: &Rc<Cell<usize>>) -> RefCell<Self> {
+            RefCell::new(Self {
+                next: None,
+                drops: drops.clone(),
+            })
+        }
+    }
+
+    impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer) {
+            if let Some(next) = &self.next {
+                tracer.mark(next);
+            }
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn sweep_frees_unreachable_objects_and_interned_strings() {
+        let mut heap = Heap::new();
+        let drops = Rc::new(Cell::new(0));
+        let root = heap.alloc(Node::new(&drops));
+        heap.alloc(Node::new(&drops));
+        let kept = heap.intern("kept");
+        heap.intern("dropped");
+        let before = heap.bytes_allocated;
+
+        let mut tracer = Tracer::new();
+        tracer.mark(&root);
+        tracer.mark(&kept);
+        heap.collect(tracer);
+
+        assert_eq!(drops.get(), 1);
+        assert_eq!(heap.objects.len(), 2);
+        assert!(heap.bytes_allocated < before);
+        assert_eq!(heap.strings.len(), 1);
+        assert!(heap.strings.contains("kept"));
+        assert!(!heap.strings.contains("dropped"));
+        assert!(Gc::ptr_eq(&heap.intern("kept"), &kept));
+    }
+
+    #[test]
+    fn collect_keeps_objects_reachable_from_roots() {
+        let mut heap = Heap::new();
+        let drops = Rc::new(Cell::new(0));
+        let root = heap.alloc(Node::new(&drops));
+        let child = heap.alloc(Node::new(&drops));
+        let grandchild = heap.alloc(Node::new(&drops));
+        root.borrow_mut().next = Some(child.clone());
+        child.borrow_mut().next = Some(grandchild.clone());
+
+        for _ in 0..2 {
+            let mut tracer = Tracer::new();
+            tracer.mark(&root);
+            heap.collect(tracer);
+        }
+
+        assert_eq!(drops.get(), 0);
+        assert_eq!(heap.objects.len(), 3);
+        let next = root.borrow().next.clone().expect("child was freed");
+        assert!(Gc::ptr_eq(&next, &child));
+        assert!(next.borrow().next.is_some());
+        assert!(!grandchild.is_marked());
+    }
+}
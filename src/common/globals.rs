@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use super::gc::{Gc, Trace, Tracer};
+
+// every global name the compiler has seen, each owning a fixed slot in the vm's global table
+#[derive(Debug, Default)]
+pub struct GlobalNames {
+    slots: HashMap<Gc<String>, usize>,
+    names: Vec<Gc<String>>,
+}
+
+impl GlobalNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // assigns the next free slot the first time a name is seen
+    pub fn slot(&mut self, name: Gc<String>) -> usize {
+        if let Some(slot) = self.slots.get(&name) {
+            return *slot;
+        }
+        self.names.push(name.clone());
+        self.slots.insert(name, self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    pub fn name(&self, slot: usize) -> &str {
+        self.names[slot].as_str()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+}
+
+impl Trace for GlobalNames {
+    fn trace(&self, tracer: &mut Tracer) {
+        for name in self.names.iter() {
+            tracer.mark(name);
+        }
+    }
+}
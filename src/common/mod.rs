@@ -1 +1,3 @@
 pub mod chunk;
+pub mod gc;
+pub mod globals;
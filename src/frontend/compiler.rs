@@ -1,13 +1,21 @@
-use std::{cell::RefCell, rc::Rc};
+use std::rc::Rc;
 
 use super::{
-    interpretation::{literal::Literal, op_codes::OpCodes},
+    interpretation::{
+        function::{Function, UpvalueLocation},
+        literal::Literal,
+        op_codes::OpCodes,
+    },
     locals::Local,
     parse_rule::{ParseRule, Rule, RuleFn},
     precedence::Precedence,
-    tokenization::{location::Location, span::Span, token::Token, tokenkind::TokenKind},
+    tokenization::{location::Location, token::Token, tokenkind::TokenKind},
+};
+use crate::{
+    common::{chunk::Chunk, gc::Heap, globals::GlobalNames},
+    error_at,
+    prelude::CompilerResult,
 };
-use crate::{common::chunk::Chunk, error_at, prelude::CompilerResult};
 
 struct LoopLocation {
     depth: usize,
@@ -19,6 +27,8 @@ struct LoopData {
     depth: usize,
     starts: Vec<LoopLocation>,
     breaks: Vec<LoopLocation>,
+    // scope depth each loop was entered at, locals deeper than it belong to the body
+    scope_depths: Vec<usize>,
 }
 
 impl LoopLocation {
@@ -27,6 +37,48 @@ impl LoopLocation {
     }
 }
 
+#[derive(PartialEq, Clone, Copy)]
+enum FunctionKind {
+    Script,
+    Function,
+    Lambda,
+    Method,
+    StaticMethod,
+    Initializer,
+}
+
+#[derive(Default)]
+struct ClassScope {
+    has_superclass: bool,
+}
+
+struct FunctionScope<'tokens> {
+    function: Function,
+    kind: FunctionKind,
+    locals: Vec<Local<'tokens>>,
+    depth: usize,
+    loop_data: LoopData,
+}
+
+impl<'tokens> FunctionScope<'tokens> {
+    fn new(kind: FunctionKind, name: impl Into<String>, file: Rc<str>) -> Self {
+        // slot zero holds the function being called, or the receiver for methods
+        let slot_name = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+        let mut slot = Local::new(slot_name, 0);
+        slot.initialized = true;
+        Self {
+            function: Function::new(name, file),
+            kind,
+            locals: vec![slot],
+            depth: 0,
+            loop_data: LoopData::default(),
+        }
+    }
+}
+
 impl Default for LoopData {
     fn default() -> Self {
         Self {
@@ -34,53 +86,59 @@ impl Default for LoopData {
             depth: 0,
             starts: Vec::new(),
             breaks: Vec::new(),
+            scope_depths: Vec::new(),
         }
     }
 }
 
 pub struct Compiler<'tokens> {
     file_path: Rc<str>,
-    source_map: Rc<RefCell<Vec<Span>>>,
-    locals: Vec<Local<'tokens>>,
-    depth: usize,
+    scopes: Vec<FunctionScope<'tokens>>,
+    classes: Vec<ClassScope>,
     tokens: &'tokens [Token],
-    chunk: Chunk,
+    // string and function constants are allocated straight into the vm's heap
+    heap: &'tokens mut Heap,
+    // shared by every compile against the same vm, so the repl keeps its globals
+    globals: &'tokens mut GlobalNames,
     current: usize,
     had_error: bool,
     panic_mode: bool,
-    loop_data: LoopData,
 }
 
 impl<'tokens> Compiler<'tokens> {
     pub fn new(
         file_path: Rc<str>,
         tokens: &'tokens Vec<Token>,
-        source_map: Rc<RefCell<Vec<Span>>>,
+        heap: &'tokens mut Heap,
+        globals: &'tokens mut GlobalNames,
     ) -> Self {
         Self {
+            scopes: vec![FunctionScope::new(
+                FunctionKind::Script,
+                "script",
+                file_path.clone(),
+            )],
             file_path,
             tokens,
-            source_map,
-            locals: Vec::new(),
-            chunk: Chunk::new(),
+            heap,
+            globals,
+            classes: Vec::new(),
             had_error: false,
             panic_mode: false,
-            loop_data: LoopData::default(),
-            depth: 0,
             current: 0,
         }
     }
 
-    pub fn compile<'chunk>(&'chunk mut self) -> CompilerResult<'chunk> {
+    pub fn compile(mut self) -> CompilerResult {
         while !self.is_match(TokenKind::EOF) {
             self.statement();
         }
-        self.end();
+        let function = self.end();
 
         if self.had_error {
             return Err(());
         }
-        Ok(&self.chunk)
+        Ok(function)
     }
 
     fn statement(&mut self) {
@@ -88,6 +146,9 @@ impl<'tokens> Compiler<'tokens> {
         match token.kind {
             TokenKind::Print => self.print_statement(),
             TokenKind::Let => self.var_decl(),
+            TokenKind::DefFn => self.fn_decl(),
+            TokenKind::Class => self.class_decl(),
+            TokenKind::Return => self.return_statement(),
             TokenKind::LeftBrace => self.block(),
             TokenKind::If => self.if_statement(),
             TokenKind::While => self.while_statement(),
@@ -104,7 +165,7 @@ impl<'tokens> Compiler<'tokens> {
 
     fn continue_statement(&mut self) {
         self.advance();
-        if !self.loop_data.inside_loop {
+        if !self.scope().loop_data.inside_loop {
             self.error(&*format!("`continue` is not allowed outside loop body"));
             return;
         }
@@ -113,12 +174,15 @@ impl<'tokens> Compiler<'tokens> {
             return;
         };
 
-        self.emit_loop(self.loop_data.starts[self.loop_data.depth - 1].location);
+        let loop_data = &self.scope().loop_data;
+        let start = loop_data.starts[loop_data.depth - 1].location;
+        self.discard_loop_locals();
+        self.emit_loop(start);
     }
 
     fn break_statement(&mut self) {
         self.advance();
-        if !self.loop_data.inside_loop {
+        if !self.scope().loop_data.inside_loop {
             self.error(&*format!("`break` is not allowed outside loop body"));
             return;
         }
@@ -127,10 +191,12 @@ impl<'tokens> Compiler<'tokens> {
             return;
         };
 
+        self.discard_loop_locals();
         let index = self.emit_jump(OpCodes::Jump(69));
-        self.loop_data
+        let loop_data = &mut self.scope_mut().loop_data;
+        loop_data
             .breaks
-            .push(LoopLocation::new(self.loop_data.depth, index));
+            .push(LoopLocation::new(loop_data.depth, index));
     }
 
     fn for_statement(&mut self) {
@@ -142,6 +208,7 @@ impl<'tokens> Compiler<'tokens> {
         ) else {
             return;
         };
+        let name = self.previous();
 
         let Ok(_) = self.consume(
             TokenKind::In,
@@ -149,65 +216,109 @@ impl<'tokens> Compiler<'tokens> {
         ) else {
             return;
         };
-        let loop_start = self.chunk.code.len();
 
         let Ok(_) = self.expression() else {
             self.error(format!("expected expresion after `{}`", self.previous().lexeme).as_str());
             return;
         };
 
-        self.statement();
+        // the iterator sits in a hidden local, `in` can never be a variable name
+        self.emit_byte(OpCodes::GetIter);
+        self.add_local("in");
+        self.mark_initialized();
+
+        let loop_start = self.chunk_len();
+        let exit = self.emit_jump(OpCodes::ForIter(0));
+        self.setup_loop(loop_start);
 
+        // a fresh binding per iteration so closures capture each value
+        self.begin_scope();
+        self.add_local(&name.lexeme);
+        self.mark_initialized();
+        self.statement();
+        self.end_scope();
         self.emit_loop(loop_start);
+
+        self.patch_jump(exit);
+        self.resolve_breaks(self.scope().loop_data.depth);
+        self.cleanup_loop();
         self.end_scope();
-        todo!();
     }
 
     fn while_statement(&mut self) {
         self.advance();
-        let loop_start = self.chunk.code.len();
+        let loop_start = self.chunk_len();
         let Ok(_) = self.expression() else {
             return;
         };
 
         let exit = self.emit_jump(OpCodes::JumpFalse(0));
-        self.setup_loop(exit);
+        self.setup_loop(loop_start);
         self.emit_byte(OpCodes::Pop);
         self.statement();
         self.emit_loop(loop_start);
 
         self.patch_jump(exit);
-        self.resolve_breaks(self.loop_data.depth);
         self.emit_byte(OpCodes::Pop);
+        // breaks skip the condition pop, it already happened on entering the body
+        self.resolve_breaks(self.scope().loop_data.depth);
         self.cleanup_loop();
     }
 
     fn resolve_breaks(&mut self, loop_depth: usize) {
-        for loc in self.loop_data.breaks.iter() {
-            if loc.depth == loop_depth {
-                let index = self.chunk.code.len() - loc.location - 1;
-                self.chunk.code[loc.location] = self
-                    .chunk
-                    .code
-                    .get(loc.location)
-                    .expect("no break here")
-                    .patch_jump(index);
-            }
+        let (breaks, rest) = self
+            .scope_mut()
+            .loop_data
+            .breaks
+            .drain(..)
+            .partition(|loc| loc.depth == loop_depth);
+        self.scope_mut().loop_data.breaks = rest;
+        for loc in breaks {
+            self.patch_jump(loc.location);
         }
     }
 
     fn setup_loop(&mut self, start: usize) {
-        self.loop_data.inside_loop = true;
-        self.loop_data.depth += 1;
-        self.loop_data
+        let depth = self.scope().depth;
+        let loop_data = &mut self.scope_mut().loop_data;
+        loop_data.inside_loop = true;
+        loop_data.depth += 1;
+        loop_data
             .starts
-            .push(LoopLocation::new(self.loop_data.depth, start))
+            .push(LoopLocation::new(loop_data.depth, start));
+        loop_data.scope_depths.push(depth);
     }
 
     fn cleanup_loop(&mut self) {
-        self.loop_data.depth -= 1;
-        if self.loop_data.depth == 0 {
-            self.loop_data.inside_loop = false;
+        let loop_data = &mut self.scope_mut().loop_data;
+        loop_data.depth -= 1;
+        loop_data.starts.pop();
+        loop_data.scope_depths.pop();
+        if loop_data.depth == 0 {
+            loop_data.inside_loop = false;
+        }
+    }
+
+    // pops the locals of the loop body before jumping out of it
+    fn discard_loop_locals(&mut self) {
+        let scope = self.scope();
+        let Some(&depth) = scope.loop_data.scope_depths.last() else {
+            return;
+        };
+
+        let captured: Vec<bool> = scope
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| local.captured)
+            .collect();
+        for captured in captured {
+            if captured {
+                self.emit_byte(OpCodes::CloseUpvalue);
+            } else {
+                self.emit_byte(OpCodes::Pop);
+            }
         }
     }
 
@@ -218,6 +329,7 @@ impl<'tokens> Compiler<'tokens> {
         };
 
         let offset = self.emit_jump(OpCodes::JumpFalse(69));
+        self.emit_byte(OpCodes::Pop);
         self.statement();
         let else_offset = self.emit_jump(OpCodes::Jump(42069));
         self.patch_jump(offset);
@@ -246,16 +358,280 @@ impl<'tokens> Compiler<'tokens> {
     }
 
     fn begin_scope(&mut self) {
-        self.depth += 1
+        self.scope_mut().depth += 1
     }
 
     fn end_scope(&mut self) {
-        self.depth -= 1;
+        self.scope_mut().depth -= 1;
 
-        while self.locals.len() > 0 && self.locals[self.locals.len() - 1].depth > self.depth {
-            self.emit_byte(OpCodes::Pop);
-            self.locals.pop();
+        loop {
+            let scope = self.scope();
+            let Some(local) = scope.locals.last() else {
+                break;
+            };
+            if local.depth <= scope.depth {
+                break;
+            }
+
+            if local.captured {
+                self.emit_byte(OpCodes::CloseUpvalue);
+            } else {
+                self.emit_byte(OpCodes::Pop);
+            }
+            self.scope_mut().locals.pop();
+        }
+    }
+
+    fn fn_decl(&mut self) {
+        self.advance();
+        let Ok(global) = self.parse_var("expected function name") else {
+            return;
+        };
+
+        // allow the function to refer to itself
+        self.mark_initialized();
+        self.function(FunctionKind::Function);
+        self.define_var(global);
+    }
+
+    fn class_decl(&mut self) {
+        self.advance();
+        let Ok(_) = self.consume(TokenKind::Identifier, "expected class name") else {
+            return;
+        };
+        let name = self.previous();
+        let index = self.identifier_constant(name);
+        self.declare_local();
+
+        self.emit_byte(OpCodes::Class(index));
+        let global = if self.scope().depth > 0 {
+            0
+        } else {
+            self.global_slot(name)
+        };
+        self.define_var(global);
+        self.classes.push(ClassScope::default());
+
+        if self.is_match(TokenKind::Less) {
+            self.superclass(name);
+        }
+
+        // keep the class on the stack while its methods are bound
+        self.named_var(name, false);
+        if self
+            .consume(TokenKind::LeftBrace, "expected `{` before class body")
+            .is_ok()
+        {
+            while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::EOF) {
+                if self.member().is_err() {
+                    break;
+                }
+            }
+            let _ = self.consume(TokenKind::RightBrace, "expected `}` after class body");
+        }
+
+        self.emit_byte(OpCodes::Pop);
+
+        let class = self.classes.pop().expect("no class scope");
+        if class.has_superclass {
+            self.end_scope();
+        }
+    }
+
+    fn superclass(&mut self, class: &Token) {
+        let Ok(_) = self.consume(TokenKind::Identifier, "expected superclass name") else {
+            return;
+        };
+        let superclass = self.previous();
+        if superclass.lexeme == class.lexeme {
+            error_at!(&superclass.span, "a class cannot inherit from itself");
+            self.error_occured();
+        }
+        self.variable(false);
+
+        // `super` lives in its own scope so each subclass captures its own superclass
+        self.begin_scope();
+        self.add_local("super");
+        self.define_var(0);
+
+        // the superclass is still the previous token, so a runtime error points at its name
+        self.named_var(class, false);
+        self.emit_byte(OpCodes::Inherit);
+        self.classes
+            .last_mut()
+            .expect("no class scope")
+            .has_superclass = true;
+    }
+
+    fn member(&mut self) -> Result<(), ()> {
+        let public = self.is_match(TokenKind::Public);
+        let is_static = self.is_match(TokenKind::Static);
+
+        let index = match self.current().kind {
+            TokenKind::DefFn => self.method(is_static)?,
+            TokenKind::Let => self.field(is_static)?,
+            _ => {
+                let token = self.current();
+                error_at!(&token.span, "expected method or field declaration");
+                self.error_occured();
+                return Err(());
+            }
+        };
+
+        if public {
+            self.emit_byte(OpCodes::Public(index));
         }
+        Ok(())
+    }
+
+    fn method(&mut self, is_static: bool) -> Result<usize, ()> {
+        self.advance();
+        self.consume(TokenKind::Identifier, "expected method name")?;
+        let name = self.previous();
+        let index = self.identifier_constant(name);
+
+        if is_static {
+            self.function(FunctionKind::StaticMethod);
+            self.emit_byte(OpCodes::StaticField(index));
+            return Ok(index);
+        }
+
+        let kind = if name.lexeme == "init" {
+            FunctionKind::Initializer
+        } else {
+            FunctionKind::Method
+        };
+        self.function(kind);
+        self.emit_byte(OpCodes::Method(index));
+        Ok(index)
+    }
+
+    fn field(&mut self, is_static: bool) -> Result<usize, ()> {
+        self.advance();
+        self.consume(TokenKind::Identifier, "expected field name")?;
+        let name = self.previous();
+        let index = self.identifier_constant(name);
+
+        if is_static {
+            self.field_value()?;
+            self.emit_byte(OpCodes::StaticField(index));
+            return Ok(index);
+        }
+
+        // instance fields are evaluated by an initializer that runs for every new instance,
+        // so no two instances share a list or map
+        self.scopes.push(FunctionScope::new(
+            FunctionKind::Method,
+            name.lexeme.clone(),
+            self.file_path.clone(),
+        ));
+        self.begin_scope();
+        let value = self.field_value();
+        if value.is_ok() {
+            self.emit_byte(OpCodes::Return);
+        }
+        self.emit_closure();
+        value?;
+        self.emit_byte(OpCodes::Field(index));
+        Ok(index)
+    }
+
+    fn field_value(&mut self) -> Result<(), ()> {
+        if self.is_match(TokenKind::Assign) {
+            self.expression()?;
+        } else {
+            self.emit_byte(OpCodes::None);
+        }
+        self.consume(TokenKind::Semicolon, "expected `;` after field declaration")
+    }
+
+    fn function(&mut self, kind: FunctionKind) {
+        let name = match kind {
+            FunctionKind::Lambda => "lambda".to_string(),
+            _ => self.previous().lexeme.clone(),
+        };
+        self.scopes
+            .push(FunctionScope::new(kind, name, self.file_path.clone()));
+        self.begin_scope();
+
+        if self.parameters().is_ok() {
+            if kind == FunctionKind::Lambda && self.is_match(TokenKind::Arrow) {
+                if self.expression().is_ok() {
+                    self.emit_byte(OpCodes::Return);
+                }
+            } else if self.check(TokenKind::LeftBrace) {
+                self.block();
+            } else if kind == FunctionKind::Lambda {
+                let token = self.current();
+                error_at!(&token.span, "expected `=>` or `{{` after lambda parameters");
+                self.error_occured();
+            } else {
+                let token = self.current();
+                error_at!(&token.span, "expected `{{` before function body");
+                self.error_occured();
+            }
+        }
+
+        self.emit_closure();
+    }
+
+    // finishes the innermost function and emits a closure over it in the enclosing one
+    fn emit_closure(&mut self) {
+        let function = self.end();
+        let function = self.heap.alloc(function);
+        let index = self
+            .chunk()
+            .add_constant_manual(Literal::Function(function));
+        self.emit_byte(OpCodes::Closure(index));
+    }
+
+    fn parameters(&mut self) -> Result<(), ()> {
+        let message = format!("expected `(` after `{}`", self.previous().lexeme);
+        self.consume(TokenKind::LeftParen, message.as_str())?;
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if self.scope().function.arity == 255 {
+                    let token = self.current();
+                    error_at!(&token.span, "cannot have more than 255 parameters");
+                    self.error_occured();
+                }
+                self.scope_mut().function.arity += 1;
+                let index = self.parse_var("expected parameter name")?;
+                self.define_var(index);
+
+                if !self.is_match(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenKind::RightParen, "expected `)` after parameters")
+    }
+
+    fn return_statement(&mut self) {
+        self.advance();
+        if self.scope().kind == FunctionKind::Script {
+            self.error("cannot return from top-level code");
+            return;
+        }
+
+        if self.is_match(TokenKind::Semicolon) {
+            self.emit_return();
+            return;
+        }
+
+        if self.scope().kind == FunctionKind::Initializer {
+            self.error("cannot return a value from an initializer");
+            return;
+        }
+
+        let Ok(_) = self.expression() else {
+            return;
+        };
+
+        let Ok(_) = self.consume(TokenKind::Semicolon, "expected `;` after return value") else {
+            return;
+        };
+        self.emit_byte(OpCodes::Return);
     }
 
     fn var_decl(&mut self) {
@@ -278,28 +654,30 @@ impl<'tokens> Compiler<'tokens> {
     }
 
     fn declare_local(&mut self) {
-        if self.depth == 0 {
+        let depth = self.scope().depth;
+        if depth == 0 {
             return;
         }
 
         let name = self.previous();
 
-        for local in self.locals.iter().rev() {
-            if local.depth < self.depth {
+        for local in self.scope().locals.iter().rev() {
+            if local.depth < depth {
                 break;
             }
 
-            if name.lexeme == local.name.lexeme {
+            if name.lexeme == local.name {
                 self.error(format!("cannot redefine variable '{}'", name.lexeme).as_str());
                 return;
             }
         }
 
-        self.add_local(name)
+        self.add_local(&name.lexeme)
     }
 
-    fn add_local(&mut self, token: &'tokens Token) {
-        self.locals.push(Local::new(token, self.depth))
+    fn add_local(&mut self, name: &'tokens str) {
+        let depth = self.scope().depth;
+        self.scope_mut().locals.push(Local::new(name, depth))
     }
 
     fn parse_var(&mut self, error_msg: &str) -> Result<usize, ()> {
@@ -308,20 +686,25 @@ impl<'tokens> Compiler<'tokens> {
         };
 
         self.declare_local();
-        if self.depth > 0 {
+        if self.scope().depth > 0 {
             return Ok(0);
         }
 
-        Ok(self.identifier_constant(self.previous()))
+        Ok(self.global_slot(self.previous()))
     }
 
     fn identifier_constant(&mut self, token: &Token) -> usize {
-        self.chunk
-            .add_constant_manual(Literal::Variable(token.lexeme.clone()))
+        let name = self.heap.intern(&token.lexeme);
+        self.chunk().add_identifier(name)
+    }
+
+    fn global_slot(&mut self, token: &Token) -> usize {
+        let name = self.heap.intern(&token.lexeme);
+        self.globals.slot(name)
     }
 
     fn define_var(&mut self, index: usize) {
-        if self.depth > 0 {
+        if self.scope().depth > 0 {
             self.mark_initialized();
             return;
         }
@@ -329,7 +712,11 @@ impl<'tokens> Compiler<'tokens> {
     }
 
     fn mark_initialized(&mut self) {
-        self.locals
+        if self.scope().depth == 0 {
+            return;
+        }
+        self.scope_mut()
+            .locals
             .last_mut()
             .expect("could not unwrap last")
             .initialized = true;
@@ -397,12 +784,26 @@ impl<'tokens> Compiler<'tokens> {
             RuleFn::Literal => self.literal(),
             RuleFn::String => self.string(),
             RuleFn::Variable => self.variable(can_assign),
+            RuleFn::Lambda => self.function(FunctionKind::Lambda),
+            RuleFn::This => self.this(),
+            RuleFn::Super => self.super_(),
+            RuleFn::List => self.list(),
+            RuleFn::Map => self.map(),
+            RuleFn::Increment => self.increment(),
+            RuleFn::FormatString => self.format_string(),
 
             // infix
             RuleFn::Binary => self.binary(),
             RuleFn::Ternary => self.ternary(),
             RuleFn::And => self.and(),
             RuleFn::Or => self.or(),
+            RuleFn::Call => self.call(),
+            RuleFn::Dot => self.dot(can_assign),
+            RuleFn::Range => self.range(),
+            RuleFn::Index => self.index(can_assign),
+
+            // postfix
+            RuleFn::PostIncrement => self.post_increment(),
         }
     }
 
@@ -434,16 +835,23 @@ impl<'tokens> Compiler<'tokens> {
             let next_prece = ParseRule::get_precedence(rule);
             if precedence <= next_prece {
                 self.advance();
-                let kind = self.previous().kind;
-                let infix_rule = ParseRule::get_infix(self.get_rule(kind));
-                self.rule_fn(infix_rule, can_assign);
+                let rule = self.get_rule(self.previous().kind);
+                match ParseRule::get_postfix(rule) {
+                    RuleFn::None => self.rule_fn(ParseRule::get_infix(rule), can_assign),
+                    postfix_rule => self.rule_fn(postfix_rule, can_assign),
+                }
             } else {
                 break;
             }
         }
 
-        if can_assign && self.is_match(TokenKind::Assign) {
+        let compound = Self::compound_operator(self.current().kind).is_some();
+        if can_assign && (self.is_match(TokenKind::Assign) || compound) {
+            if compound {
+                self.advance();
+            }
             error_at!(&self.previous().span, "invalid assignment target");
+            self.error_occured();
             return Err(());
         }
         Ok(())
@@ -484,41 +892,142 @@ impl<'tokens> Compiler<'tokens> {
         self.named_var(self.previous(), can_assign);
     }
 
-    fn resolve_local(&mut self, token: &Token) -> Option<usize> {
-        for (i, local) in self.locals.iter().rev().enumerate() {
-            if local.name.lexeme == token.lexeme {
-                if !local.initialized {
-                    self.error("cannot read local variable in its own initializer");
-                }
-                return Some(self.locals.len() - i - 1);
-            }
+    fn resolve_local(&mut self, scope: usize, token: &Token) -> Option<usize> {
+        let locals = &self.scopes[scope].locals;
+        let index = locals
+            .iter()
+            .rposition(|local| local.name == token.lexeme)?;
+        if !locals[index].initialized {
+            self.error("cannot read local variable in its own initializer");
         }
-        None
+        Some(index)
     }
 
-    fn named_var(&mut self, token: &Token, can_assign: bool) {
-        let index = self.identifier_constant(token);
-        let mut get_op = OpCodes::GetGlobal(index);
-        let mut set_op = OpCodes::SetGlobal(index);
+    fn resolve_upvalue(&mut self, scope: usize, token: &Token) -> Option<usize> {
+        if scope == 0 {
+            return None;
+        }
 
-        if let Some(local_index) = self.resolve_local(token) {
-            get_op = OpCodes::GetLocal(local_index);
-            set_op = OpCodes::SetLocal(local_index);
+        if let Some(local) = self.resolve_local(scope - 1, token) {
+            self.scopes[scope - 1].locals[local].captured = true;
+            return Some(self.add_upvalue(scope, UpvalueLocation::new(local, true)));
         }
 
+        let upvalue = self.resolve_upvalue(scope - 1, token)?;
+        Some(self.add_upvalue(scope, UpvalueLocation::new(upvalue, false)))
+    }
+
+    fn add_upvalue(&mut self, scope: usize, location: UpvalueLocation) -> usize {
+        let upvalues = &mut self.scopes[scope].function.upvalues;
+        if let Some(index) = upvalues.iter().position(|upvalue| *upvalue == location) {
+            return index;
+        }
+
+        if upvalues.len() == 256 {
+            self.error("too many closure variables in function");
+            return 0;
+        }
+        upvalues.push(location);
+        upvalues.len() - 1
+    }
+
+    fn variable_ops(&mut self, token: &Token) -> (OpCodes, OpCodes) {
+        let scope = self.scopes.len() - 1;
+        if let Some(index) = self.resolve_local(scope, token) {
+            (OpCodes::GetLocal(index), OpCodes::SetLocal(index))
+        } else if let Some(index) = self.resolve_upvalue(scope, token) {
+            (OpCodes::GetUpvalue(index), OpCodes::SetUpvalue(index))
+        } else {
+            let slot = self.global_slot(token);
+            (OpCodes::GetGlobal(slot), OpCodes::SetGlobal(slot))
+        }
+    }
+
+    fn named_var(&mut self, token: &Token, can_assign: bool) {
+        let (get_op, set_op) = self.variable_ops(token);
+
+        let compound = Self::compound_operator(self.current().kind);
         if can_assign && self.is_match(TokenKind::Assign) {
             let _ = self.expression();
             self.emit_byte(set_op);
+        } else if let (true, Some(operator)) = (can_assign, compound) {
+            // the target is only resolved once, `x += 1` reads and writes the same slot
+            self.advance();
+            self.emit_byte(get_op);
+            let _ = self.expression();
+            self.emit_byte(operator);
+            self.emit_byte(set_op);
+        } else if let Some(operator) = Self::postfix_operator(self.current().kind) {
+            // `x++` leaves the old value on the stack, assignment context does not matter here
+            self.advance();
+            self.emit_byte(get_op.clone());
+            self.emit_byte(get_op);
+            self.emit_constant(Literal::Int(1));
+            self.emit_byte(operator);
+            self.emit_byte(set_op);
+            self.emit_byte(OpCodes::Pop);
         } else {
             self.emit_byte(get_op);
         }
     }
 
+    // `++x` and `--x`, evaluates to the updated value
+    fn increment(&mut self) {
+        let operator = self.previous();
+        let Ok(_) = self.consume(
+            TokenKind::Identifier,
+            format!("expected variable after `{}`", operator.lexeme).as_str(),
+        ) else {
+            return;
+        };
+
+        let (get_op, set_op) = self.variable_ops(self.previous());
+        self.emit_byte(get_op);
+        self.emit_constant(Literal::Int(1));
+        self.emit_byte(Self::increment_operator(operator.kind));
+        self.emit_byte(set_op);
+    }
+
+    // `x++` on a bare variable is compiled by `named_var`, anything else reaching here is not assignable
+    fn post_increment(&mut self) {
+        let operator = self.previous();
+        error_at!(&operator.span, "invalid `{}` target", operator.lexeme);
+        self.error_occured();
+    }
+
+    fn increment_operator(kind: TokenKind) -> OpCodes {
+        match kind {
+            TokenKind::MinusMinus => OpCodes::Subtract,
+            _ => OpCodes::Add,
+        }
+    }
+
+    fn postfix_operator(kind: TokenKind) -> Option<OpCodes> {
+        match kind {
+            TokenKind::PlusPlus | TokenKind::MinusMinus => Some(Self::increment_operator(kind)),
+            _ => None,
+        }
+    }
+
+    fn compound_operator(kind: TokenKind) -> Option<OpCodes> {
+        match kind {
+            TokenKind::PlusEqual => Some(OpCodes::Add),
+            TokenKind::MinusEqual => Some(OpCodes::Subtract),
+            TokenKind::StarEqual => Some(OpCodes::Multiply),
+            TokenKind::SlashEqual => Some(OpCodes::Divide),
+            TokenKind::ModEqual => Some(OpCodes::Modulo),
+            _ => None,
+        }
+    }
+
     fn number(&mut self) {
         let token = self.previous();
         match token.kind {
             TokenKind::Int => {
-                let value = token.lexeme.parse::<isize>().expect("Failed to parse int.");
+                let Ok(value) = token.lexeme.parse::<isize>() else {
+                    self.error("integer literal is too large");
+                    return;
+                };
                 self.emit_constant(Literal::Int(value))
             }
             TokenKind::Float => {
@@ -542,10 +1051,44 @@ impl<'tokens> Compiler<'tokens> {
     fn string(&mut self) {
         let token = self.previous();
         let len = token.lexeme.len() - 1;
-        let literal = token.lexeme[1..len].chars().collect();
+        let literal = self.heap.intern(&token.lexeme[1..len]);
         self.emit_constant(Literal::String(literal))
     }
 
+    fn format_string(&mut self) {
+        let mut parts = 0;
+        loop {
+            let piece = self.previous();
+            if !piece.lexeme.is_empty() {
+                let literal = self.heap.intern(&piece.lexeme);
+                self.emit_constant(Literal::String(literal));
+                parts += 1;
+            }
+            if piece.kind == TokenKind::FormatStringEnd {
+                break;
+            }
+
+            // a bad interpolation has already been reported
+            let Ok(()) = self.expression() else {
+                return;
+            };
+            if self.panic_mode {
+                return;
+            }
+            parts += 1;
+
+            if !self.is_match(TokenKind::FormatStringPart)
+                && !self.is_match(TokenKind::FormatStringEnd)
+            {
+                let token = self.current();
+                error_at!(&token.span, "expected `}}` after interpolated expression");
+                self.error_occured();
+                return;
+            }
+        }
+        self.emit_byte(OpCodes::Concat(parts));
+    }
+
     fn grouping(&mut self) {
         let left_paren = self.previous();
         let Ok(()) = self.expression() else {
@@ -573,18 +1116,21 @@ impl<'tokens> Compiler<'tokens> {
             return;
         };
 
-        match kind {
-            TokenKind::Minus => self.emit_byte(OpCodes::Negate),
-            TokenKind::Bang => self.emit_byte(OpCodes::Not),
-            _ => (),
-        }
+        let op_code = match kind {
+            TokenKind::Minus => OpCodes::Negate,
+            TokenKind::Bang => OpCodes::Not,
+            TokenKind::Tilde => OpCodes::BitNot,
+            _ => return,
+        };
+        // runtime errors point at the operator rather than the end of the operand
+        self.emit_byte_at(op_code, token.span.location);
     }
 
     fn binary(&mut self) {
         let operator = self.previous();
         let kind = operator.kind;
         let rule = self.get_rule(kind);
-        let Ok(()) = self.parse_precedence(ParseRule::get_precedence(rule)) else {
+        let Ok(()) = self.parse_precedence(ParseRule::get_precedence(rule).next()) else {
             error_at!(
                 &operator.span,
                 "expected expression after '{}'",
@@ -593,22 +1139,228 @@ impl<'tokens> Compiler<'tokens> {
             return;
         };
 
-        match kind {
-            TokenKind::Minus => self.emit_byte(OpCodes::Subtract),
-            TokenKind::Plus => self.emit_byte(OpCodes::Add),
-            TokenKind::Star => self.emit_byte(OpCodes::Multiply),
-            TokenKind::Slash => self.emit_byte(OpCodes::Divide),
-            TokenKind::Equals => self.emit_byte(OpCodes::Equals),
-            TokenKind::BangEqual => self.emit_byte(OpCodes::NotEquals),
-            TokenKind::Greater => self.emit_byte(OpCodes::Greater),
-            TokenKind::GreaterEqual => self.emit_byte(OpCodes::GreaterEquals),
-            TokenKind::Less => self.emit_byte(OpCodes::Less),
-            TokenKind::LessEqual => self.emit_byte(OpCodes::LessEquals),
+        let op_code = match kind {
+            TokenKind::Minus => OpCodes::Subtract,
+            TokenKind::Plus => OpCodes::Add,
+            TokenKind::Star => OpCodes::Multiply,
+            TokenKind::Slash => OpCodes::Divide,
+            TokenKind::Modulus => OpCodes::Modulo,
+            TokenKind::Equals => OpCodes::Equals,
+            TokenKind::BangEqual => OpCodes::NotEquals,
+            TokenKind::Greater => OpCodes::Greater,
+            TokenKind::GreaterEqual => OpCodes::GreaterEquals,
+            TokenKind::Less => OpCodes::Less,
+            TokenKind::LessEqual => OpCodes::LessEquals,
+            TokenKind::In => OpCodes::In,
+            TokenKind::Ampersand => OpCodes::BitAnd,
+            TokenKind::Bar => OpCodes::BitOr,
+            TokenKind::Caret => OpCodes::BitXor,
+            TokenKind::ShiftLeft => OpCodes::ShiftLeft,
+            TokenKind::ShiftRight => OpCodes::ShiftRight,
+            _ => return,
+        };
+        self.emit_byte_at(op_code, operator.span.location);
+    }
+
+    fn range(&mut self) {
+        let operator = self.previous();
+        let Ok(()) = self.parse_precedence(Precedence::Shift) else {
+            error_at!(
+                &operator.span,
+                "expected expression after '{}'",
+                operator.lexeme
+            );
+            return;
+        };
+
+        // `step` is only a keyword right after a range
+        if self.check(TokenKind::Identifier) && self.current().lexeme == "step" {
+            self.advance();
+            let step = self.previous();
+            let Ok(()) = self.parse_precedence(Precedence::Shift) else {
+                error_at!(&step.span, "expected expression after 'step'");
+                return;
+            };
+        } else {
+            self.emit_constant(Literal::Int(1));
+        }
+
+        let op_code = match operator.kind {
+            TokenKind::DotDotEqual => OpCodes::RangeInclusive,
+            _ => OpCodes::Range,
+        };
+        self.emit_byte_at(op_code, operator.span.location);
+    }
+
+    fn this(&mut self) {
+        if self.classes.is_empty() {
+            self.error("cannot use `this` outside of a class");
+            return;
+        }
+        if self.in_static_method() {
+            self.error("cannot use `this` in a static method");
+            return;
+        }
+        self.variable(false);
+    }
+
+    fn in_static_method(&self) -> bool {
+        let method = self.scopes.iter().rev().find(|scope| {
+            matches!(
+                scope.kind,
+                FunctionKind::Method | FunctionKind::Initializer | FunctionKind::StaticMethod
+            )
+        });
+        matches!(method, Some(scope) if scope.kind == FunctionKind::StaticMethod)
+    }
+
+    fn super_(&mut self) {
+        match self.classes.last() {
+            None => {
+                self.error("cannot use `super` outside of a class");
+                return;
+            }
+            Some(class) if !class.has_superclass => {
+                self.error("cannot use `super` in a class with no superclass");
+                return;
+            }
+            _ if self.in_static_method() => {
+                self.error("cannot use `super` in a static method");
+                return;
+            }
             _ => (),
         }
+
+        let Ok(_) = self.consume(TokenKind::Dot, "expected `.` after `super`") else {
+            return;
+        };
+        let Ok(_) = self.consume(TokenKind::Identifier, "expected superclass method name") else {
+            return;
+        };
+        let index = self.identifier_constant(self.previous());
+
+        self.named_var(&self.synthetic_token("this"), false);
+        self.named_var(&self.synthetic_token("super"), false);
+        self.emit_byte(OpCodes::GetSuper(index));
     }
 
+    fn dot(&mut self, can_assign: bool) {
+        let Ok(_) = self.consume(TokenKind::Identifier, "expected property name after `.`") else {
+            return;
+        };
+        let index = self.identifier_constant(self.previous());
+
+        if can_assign && self.is_match(TokenKind::Assign) {
+            let _ = self.expression();
+            self.emit_byte(OpCodes::SetProperty(index));
+        } else {
+            self.emit_byte(OpCodes::GetProperty(index));
+        }
+    }
+
+    fn list(&mut self) {
+        let mut count = 0;
+        if !self.check(TokenKind::RightBracket) {
+            loop {
+                let Ok(()) = self.expression() else {
+                    return;
+                };
+                count += 1;
+
+                // allow a trailing comma
+                if !self.is_match(TokenKind::Comma) || self.check(TokenKind::RightBracket) {
+                    break;
+                }
+            }
+        }
+        let Ok(_) = self.consume(TokenKind::RightBracket, "expected `]` after list elements")
+        else {
+            return;
+        };
+        self.emit_byte(OpCodes::BuildList(count));
+    }
+
+    // a `{` in statement position is always a block, so this only runs for expressions
+    fn map(&mut self) {
+        let mut count = 0;
+        if !self.check(TokenKind::RightBrace) {
+            loop {
+                let Ok(()) = self.expression() else {
+                    return;
+                };
+                let Ok(_) = self.consume(TokenKind::Colon, "expected `:` after map key") else {
+                    return;
+                };
+                let Ok(()) = self.expression() else {
+                    return;
+                };
+                count += 1;
+
+                if !self.is_match(TokenKind::Comma) || self.check(TokenKind::RightBrace) {
+                    break;
+                }
+            }
+        }
+        let Ok(_) = self.consume(TokenKind::RightBrace, "expected `}` after map entries") else {
+            return;
+        };
+        self.emit_byte(OpCodes::BuildMap(count));
+    }
+
+    fn index(&mut self, can_assign: bool) {
+        let Ok(()) = self.expression() else {
+            return;
+        };
+        let Ok(_) = self.consume(TokenKind::RightBracket, "expected `]` after index") else {
+            return;
+        };
+
+        if can_assign && self.is_match(TokenKind::Assign) {
+            let _ = self.expression();
+            self.emit_byte(OpCodes::SetIndex);
+        } else {
+            self.emit_byte(OpCodes::GetIndex);
+        }
+    }
+
+    fn call(&mut self) {
+        let open = self.previous().span.location;
+        let Ok(arg_count) = self.argument_list() else {
+            return;
+        };
+        // a stack trace points at the whole argument list when it fits on one line
+        let close = self.previous().span.location;
+        let location = if open.line == close.line {
+            Location::new(open.line, open.start, close.end)
+        } else {
+            open
+        };
+        self.emit_byte_at(OpCodes::Call(arg_count), location);
+    }
+
+    fn argument_list(&mut self) -> Result<usize, ()> {
+        let mut count = 0;
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                self.expression()?;
+                if count == 255 {
+                    self.error("cannot have more than 255 arguments");
+                }
+                count += 1;
+
+                if !self.is_match(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenKind::RightParen, "expected `)` after arguments")?;
+        Ok(count)
+    }
+
+    // lowered like `if_statement` so only the selected branch is evaluated
     fn ternary(&mut self) {
+        let then_jump = self.emit_jump(OpCodes::JumpFalse(0));
+        self.emit_byte(OpCodes::Pop);
         let Ok(()) = self.expression() else {
             let token = self.previous();
             error_at!(&token.span, "expected expression after `{}`", token.lexeme);
@@ -619,58 +1371,48 @@ impl<'tokens> Compiler<'tokens> {
             return;
         };
 
+        let else_jump = self.emit_jump(OpCodes::Jump(0));
+        self.patch_jump(then_jump);
+        self.emit_byte(OpCodes::Pop);
         let Ok(()) = self.expression() else {
             let token = self.previous();
             error_at!(&token.span, "expected expression after `{}`", token.lexeme);
             return;
         };
-        self.emit_byte(OpCodes::Ternary);
+        self.patch_jump(else_jump);
     }
 
     fn expression(&mut self) -> Result<(), ()> {
-        let line = self.current().span.location.line;
-        let start = self.current().span.location.start;
-        self.parse_precedence(Precedence::Assignment)?;
-        let end = self.previous().span.location.end; // ignore semicolon
-        self.map_source(line, start, end);
-        Ok(())
-    }
-
-    fn map_source(&self, line: u32, start: usize, end: usize) {
-        self.source_map.borrow_mut().push(Span::new(
-            self.file_path.clone(),
-            Location::new(line, start, end),
-        ));
+        self.parse_precedence(Precedence::Assignment)
     }
 
     // makeConstant
     fn emit_constant(&mut self, constant: Literal) {
-        self.chunk
-            .add_constant(constant, self.current().span.location.line)
+        let location = self.current().span.location;
+        self.chunk().add_constant(constant, location)
     }
 
     fn emit_byte(&mut self, code: OpCodes) {
-        self.chunk.write(code, self.previous().span.location.line)
+        let location = self.previous().span.location;
+        self.emit_byte_at(code, location);
+    }
+
+    fn emit_byte_at(&mut self, code: OpCodes, location: Location) {
+        self.chunk().write(code, location)
     }
 
     fn emit_jump(&mut self, code: OpCodes) -> usize {
-        self.emit_byte(code);
-        self.chunk.code.len() - 1
+        let location = self.previous().span.location;
+        self.chunk().write_jump(code, location)
     }
 
     fn patch_jump(&mut self, offset: usize) {
-        let index = self.chunk.code.len() - offset - 1;
-        self.chunk.code[offset] = self
-            .chunk
-            .code
-            .get(offset)
-            .expect("no jump 2")
-            .patch_jump(index);
+        self.chunk().patch_jump(offset)
     }
 
     fn emit_loop(&mut self, start: usize) {
-        let offset = self.chunk.code.len() - start + 1;
-        self.emit_byte(OpCodes::Loop(offset));
+        let location = self.previous().span.location;
+        self.chunk().write_loop(start, location)
     }
 
     fn advance(&mut self) {
@@ -711,10 +1453,36 @@ impl<'tokens> Compiler<'tokens> {
         error_at!(&token.span, "{msg}");
     }
 
-    fn end(&mut self) {
+    fn emit_return(&mut self) {
+        if self.scope().kind == FunctionKind::Initializer {
+            self.emit_byte(OpCodes::GetLocal(0));
+        } else {
+            self.emit_byte(OpCodes::None);
+        }
         self.emit_byte(OpCodes::Return);
     }
 
+    fn end(&mut self) -> Function {
+        self.emit_return();
+        self.scopes.pop().expect("no function scope").function
+    }
+
+    fn synthetic_token(&self, lexeme: &str) -> Token {
+        Token::new(TokenKind::Identifier, self.previous().span.dup(), lexeme)
+    }
+
+    fn scope(&self) -> &FunctionScope<'tokens> {
+        self.scopes.last().expect("no function scope")
+    }
+
+    fn scope_mut(&mut self) -> &mut FunctionScope<'tokens> {
+        self.scopes.last_mut().expect("no function scope")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.scope_mut().function.chunk
+    }
+
     fn current(&self) -> &'tokens Token {
         &self.tokens[self.current]
     }
@@ -733,7 +1501,7 @@ impl<'tokens> Compiler<'tokens> {
     }
 
     fn chunk_len(&self) -> usize {
-        self.chunk.code.len()
+        self.scope().function.chunk.code.len()
     }
 
     fn is_next_end_error(&mut self, token: &Token, message: String) -> Result<(), ()> {
@@ -0,0 +1,193 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use super::{closure::Closure, literal::Literal};
+use crate::common::gc::{Gc, Trace, Tracer};
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<String, Gc<Closure>>,
+    pub superclass: Option<Gc<RefCell<Class>>>,
+    // instance field initializers in declaration order, run once for every new instance
+    pub fields: Vec<(String, Gc<Closure>)>,
+    pub statics: HashMap<String, Literal>,
+    pub public: HashSet<String>,
+    // instance fields not declared in the class body but first assigned by one of its methods
+    pub assigned: HashSet<String>,
+}
+
+impl Class {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            methods: HashMap::new(),
+            superclass: None,
+            fields: Vec::new(),
+            statics: HashMap::new(),
+            public: HashSet::new(),
+            assigned: HashSet::new(),
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Gc<Closure>> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(method.clone());
+        }
+        self.superclass.as_ref()?.borrow().find_method(name)
+    }
+
+    pub fn find_static(&self, name: &str) -> Option<Literal> {
+        if let Some(value) = self.statics.get(name) {
+            return Some(value.clone());
+        }
+        self.superclass.as_ref()?.borrow().find_static(name)
+    }
+
+    // assigns to the class declaring the static, or to this class if none does
+    pub fn set_static(&mut self, name: &str, value: Literal) {
+        if !self.statics.contains_key(name) {
+            if let Some(superclass) = &self.superclass {
+                if superclass.borrow().find_static(name).is_some() {
+                    superclass.borrow_mut().set_static(name, value);
+                    return;
+                }
+            }
+        }
+        self.statics.insert(name.to_string(), value);
+    }
+
+    pub fn is_public(&self, name: &str) -> bool {
+        if self.public.contains(name) {
+            return true;
+        }
+        match &self.superclass {
+            Some(superclass) => superclass.borrow().is_public(name),
+            None => false,
+        }
+    }
+
+    // superclass initializers come first so a subclass can redeclare a field
+    pub fn field_initializers(&self) -> Vec<(String, Gc<Closure>)> {
+        let mut fields = match &self.superclass {
+            Some(superclass) => superclass.borrow().field_initializers(),
+            None => Vec::new(),
+        };
+        fields.extend(self.fields.iter().cloned());
+        fields
+    }
+
+    // whether the member `name` is declared by this class itself rather than a superclass
+    pub fn declares(&self, name: &str) -> bool {
+        self.methods.contains_key(name)
+            || self.statics.contains_key(name)
+            || self.fields.iter().any(|(field, _)| field == name)
+            || self.assigned.contains(name)
+    }
+
+    // nearest class in the chain declaring `name`, `class` itself if none does
+    pub fn declaring(class: &Gc<RefCell<Class>>, name: &str) -> Gc<RefCell<Class>> {
+        let mut current = Some(class.clone());
+        while let Some(ancestor) = current {
+            if ancestor.borrow().declares(name) {
+                return ancestor;
+            }
+            current = ancestor.borrow().superclass.clone();
+        }
+        class.clone()
+    }
+
+    // whether `ancestor` is `class` or one of its superclasses
+    pub fn inherits(class: &Gc<RefCell<Class>>, ancestor: &Gc<RefCell<Class>>) -> bool {
+        let mut current = Some(class.clone());
+        while let Some(class) = current {
+            if Gc::ptr_eq(&class, ancestor) {
+                return true;
+            }
+            current = class.borrow().superclass.clone();
+        }
+        false
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, tracer: &mut Tracer) {
+        for method in self.methods.values() {
+            tracer.mark(method);
+        }
+        if let Some(superclass) = &self.superclass {
+            tracer.mark(superclass);
+        }
+        for (_, initializer) in self.fields.iter() {
+            tracer.mark(initializer);
+        }
+        for value in self.statics.values() {
+            value.trace(tracer);
+        }
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Gc<RefCell<Class>>,
+    pub fields: HashMap<String, Literal>,
+}
+
+impl Instance {
+    // fields are filled in by the vm running the class's field initializers
+    pub fn new(class: Gc<RefCell<Class>>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+}
+
+impl Trace for Instance {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.class);
+        for value in self.fields.values() {
+            value.trace(tracer);
+        }
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} instance>", self.class.borrow().name)
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Literal,
+    pub method: Gc<Closure>,
+}
+
+impl BoundMethod {
+    pub fn new(receiver: Literal, method: Gc<Closure>) -> Self {
+        Self { receiver, method }
+    }
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.receiver.trace(tracer);
+        tracer.mark(&self.method);
+    }
+}
+
+impl Display for BoundMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.method)
+    }
+}
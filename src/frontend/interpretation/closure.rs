@@ -0,0 +1,63 @@
+use std::{cell::RefCell, fmt::Display};
+
+use super::{class::Class, function::Function, literal::Literal};
+use crate::common::gc::{Gc, Trace, Tracer};
+
+#[derive(Debug)]
+pub enum Upvalue {
+    // still lives on the stack at this slot
+    Open(usize),
+    Closed(Literal),
+}
+
+impl Trace for Upvalue {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Self::Closed(value) = self {
+            value.trace(tracer);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Gc<Function>,
+    pub upvalues: Vec<Gc<RefCell<Upvalue>>>,
+    // class whose private members this closure may access
+    pub owner: Option<Gc<RefCell<Class>>>,
+}
+
+impl Closure {
+    pub fn new(function: Gc<Function>, upvalues: Vec<Gc<RefCell<Upvalue>>>) -> Self {
+        Self {
+            function,
+            upvalues,
+            owner: None,
+        }
+    }
+
+    pub fn with_owner(&self, owner: Gc<RefCell<Class>>) -> Self {
+        Self {
+            function: self.function.clone(),
+            upvalues: self.upvalues.clone(),
+            owner: Some(owner),
+        }
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.function);
+        for upvalue in self.upvalues.iter() {
+            tracer.mark(upvalue);
+        }
+        if let Some(owner) = &self.owner {
+            tracer.mark(owner);
+        }
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.function)
+    }
+}
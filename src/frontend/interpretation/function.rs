@@ -1,25 +1,70 @@
-use std::fmt::Display;
+use std::{fmt::Display, mem, rc::Rc};
 
-use crate::common::chunk::Chunk;
+use super::{literal::Literal, op_codes::OpCodes};
+use crate::common::{
+    chunk::Chunk,
+    gc::{Trace, Tracer},
+};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpvalueLocation {
+    pub index: usize,
+    // captures a local of the enclosing function rather than one of its upvalues
+    pub is_local: bool,
+}
+
+impl UpvalueLocation {
+    pub fn new(index: usize, is_local: bool) -> Self {
+        Self { index, is_local }
+    }
+}
+
+#[derive(Debug)]
 pub struct Function {
-    arity: u32,
-    chunk: Chunk,
-    name: String,
+    pub arity: u32,
+    pub chunk: Chunk,
+    pub name: String,
+    // source file the function was compiled from, for stack traces
+    pub file: Rc<str>,
+    pub upvalues: Vec<UpvalueLocation>,
 }
 
-impl Function {}
+impl Function {
+    pub fn new(name: impl Into<String>, file: Rc<str>) -> Self {
+        Self {
+            name: name.into(),
+            file,
+            ..Default::default()
+        }
+    }
+}
 
 impl Default for Function {
     fn default() -> Self {
         Self {
             arity: 0,
             name: "".into(),
+            file: "".into(),
             chunk: Chunk::new(),
+            upvalues: Vec::new(),
         }
     }
 }
 
+// constants are only reachable through the function that owns them
+impl Trace for Function {
+    fn trace(&self, tracer: &mut Tracer) {
+        for constant in self.chunk.constants.iter() {
+            constant.trace(tracer);
+        }
+    }
+
+    fn extra_size(&self) -> usize {
+        self.chunk.code.capacity() * mem::size_of::<OpCodes>()
+            + self.chunk.constants.capacity() * mem::size_of::<Literal>()
+    }
+}
+
 impl Display for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<fn {}>", self.name)
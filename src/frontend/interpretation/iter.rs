@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+use std::cell::RefCell;
+
+use super::{literal::Literal, range::Range};
+use crate::common::gc::{Gc, Heap, Trace, Tracer};
+
+// state of a running `for` loop
+#[derive(Debug)]
+pub enum Iter {
+    // `next` is none once stepping past the end would overflow
+    Range {
+        range: Range,
+        next: Option<isize>,
+    },
+    Chars {
+        chars: Vec<char>,
+        index: usize,
+    },
+    List {
+        list: Gc<RefCell<Vec<Literal>>>,
+        index: usize,
+    },
+    // user object exposing `has_next` and `next` methods
+    Object(Literal),
+}
+
+impl Iter {
+    // yields the next value of a native iterator, objects are driven by the vm
+    pub fn next(&mut self, heap: &mut Heap) -> Option<Literal> {
+        match self {
+            Self::Range { range, next } => {
+                let value = next.filter(|value| range.in_bounds(*value))?;
+                *next = value.checked_add(range.step);
+                Some(Literal::Int(value))
+            }
+            Self::Chars { chars, index } => {
+                let ch = chars.get(*index)?;
+                *index += 1;
+                Some(Literal::String(heap.intern(ch.encode_utf8(&mut [0; 4]))))
+            }
+            Self::List { list, index } => {
+                let item = list.borrow().get(*index)?.clone();
+                *index += 1;
+                Some(item)
+            }
+            Self::Object(_) => None,
+        }
+    }
+}
+
+impl Trace for Iter {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Self::List { list, .. } => tracer.mark(list),
+            Self::Object(object) => object.trace(tracer),
+            _ => (),
+        }
+    }
+}
+
+impl Display for Iter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<iterator>")
+    }
+}
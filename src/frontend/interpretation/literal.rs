@@ -1,5 +1,19 @@
+use std::cell::RefCell;
 use std::fmt::Display;
-use std::ops::{Add, Div, Mul, Sub};
+use std::mem;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Rem, Shl, Shr, Sub};
+
+use crate::common::gc::{Gc, Trace, Tracer};
+
+use super::{
+    class::{BoundMethod, Class, Instance},
+    closure::Closure,
+    function::Function,
+    iter::Iter,
+    map::Map,
+    native::Native,
+    range::Range,
+};
 
 #[derive(Debug, Clone)]
 pub enum Literal {
@@ -7,8 +21,49 @@ pub enum Literal {
     Float(f64),
     Int(isize),
     Bool(bool),
-    String(String),
-    Variable(String),
+    // always interned, so strings compare by pointer
+    String(Gc<String>),
+    Variable(Gc<String>),
+    Function(Gc<Function>),
+    Closure(Gc<Closure>),
+    Native(Gc<Native>),
+    Class(Gc<RefCell<Class>>),
+    Instance(Gc<RefCell<Instance>>),
+    BoundMethod(Gc<BoundMethod>),
+    Iterator(Gc<RefCell<Iter>>),
+    Range(Range),
+    List(Gc<RefCell<Vec<Literal>>>),
+    Map(Gc<RefCell<Map>>),
+}
+
+impl Trace for Literal {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Self::String(v) | Self::Variable(v) => tracer.mark(v),
+            Self::Function(v) => tracer.mark(v),
+            Self::Closure(v) => tracer.mark(v),
+            Self::Native(v) => tracer.mark(v),
+            Self::Class(v) => tracer.mark(v),
+            Self::Instance(v) => tracer.mark(v),
+            Self::BoundMethod(v) => tracer.mark(v),
+            Self::Iterator(v) => tracer.mark(v),
+            Self::List(v) => tracer.mark(v),
+            Self::Map(v) => tracer.mark(v),
+            _ => (),
+        }
+    }
+}
+
+impl Trace for Vec<Literal> {
+    fn trace(&self, tracer: &mut Tracer) {
+        for item in self.iter() {
+            item.trace(tracer);
+        }
+    }
+
+    fn extra_size(&self) -> usize {
+        self.capacity() * mem::size_of::<Literal>()
+    }
 }
 
 impl Display for Literal {
@@ -19,26 +74,62 @@ impl Display for Literal {
             Self::Bool(v) => write!(f, "{v}"),
             Self::String(v) => write!(f, "{v}"),
             Self::Variable(v) => write!(f, "{v}"),
+            Self::Function(v) => write!(f, "{v}"),
+            Self::Closure(v) => write!(f, "{v}"),
+            Self::Native(v) => write!(f, "{v}"),
+            Self::Class(v) => write!(f, "{}", v.borrow()),
+            Self::Instance(v) => write!(f, "{}", v.borrow()),
+            Self::BoundMethod(v) => write!(f, "{v}"),
+            Self::Iterator(v) => write!(f, "{}", v.borrow()),
+            Self::Range(v) => write!(f, "{v}"),
+            Self::List(v) => {
+                write!(f, "[")?;
+                for (i, item) in v.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Map(v) => write!(f, "{}", v.borrow()),
             Self::None => write!(f, "none"),
         }
     }
 }
 
 impl Literal {
-    pub fn negate(self) -> Self {
+    pub fn negate(self) -> Result<Self, String> {
         match self {
-            Self::Float(v) => Self::Float(-v),
-            Self::Int(v) => Self::Int(-v),
+            Self::Float(v) => Ok(Self::Float(-v)),
+            Self::Int(v) => checked(v.checked_neg()),
             _ => panic!("Cannot negate non-number."),
         }
     }
 
+    pub fn bit_not(self) -> Result<Self, String> {
+        match self {
+            Self::Int(v) => Ok(Self::Int(!v)),
+            _ => Err(format!("Cannot apply `~` to type {}", self.type_name())),
+        }
+    }
+
     pub fn truthy(&self) -> bool {
         match self {
             Self::Float(v) => *v != 0.,
             Self::Int(v) => *v != 0,
             Self::Bool(v) => *v,
             Self::String(v) => v.len() > 0,
+            Self::Function(_) => true,
+            Self::Closure(_) => true,
+            Self::Native(_) => true,
+            Self::Class(_) => true,
+            Self::Instance(_) => true,
+            Self::BoundMethod(_) => true,
+            Self::Iterator(_) => true,
+            Self::Range(v) => !v.is_empty(),
+            Self::List(v) => !v.borrow().is_empty(),
+            Self::Map(v) => !v.borrow().is_empty(),
             Self::None => false,
             _ => unreachable!("variable ?")
         }
@@ -51,6 +142,16 @@ impl Literal {
             Self::Bool(_) => "bool",
             Self::String(_) => "string",
             Self::Variable(_) => "identifier",
+            Self::Function(_) => "function",
+            Self::Closure(_) => "function",
+            Self::Native(_) => "function",
+            Self::Class(_) => "class",
+            Self::Instance(_) => "instance",
+            Self::BoundMethod(_) => "method",
+            Self::Iterator(_) => "iterator",
+            Self::Range(_) => "range",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
             Self::None => "none",
         }
         .to_string()
@@ -71,6 +172,16 @@ impl Literal {
             Self::None => Self::Bool(true),
             Self::Bool(v) => Self::Bool(!v),
             Self::String(v) => Self::Bool(v.len() == 0),
+            Self::Function(_) => Self::Bool(false),
+            Self::Closure(_) => Self::Bool(false),
+            Self::Native(_) => Self::Bool(false),
+            Self::Class(_) => Self::Bool(false),
+            Self::Instance(_) => Self::Bool(false),
+            Self::BoundMethod(_) => Self::Bool(false),
+            Self::Iterator(_) => Self::Bool(false),
+            Self::Range(v) => Self::Bool(v.is_empty()),
+            Self::List(v) => Self::Bool(v.borrow().is_empty()),
+            Self::Map(v) => Self::Bool(v.borrow().is_empty()),
             _ => self
         }
     }
@@ -86,6 +197,16 @@ impl Literal {
             (Self::Int(_), Self::Bool(_)) |
             (Self::None, Self::None) |
             (Self::String(_), Self::String(_)) |
+            (Self::Function(_), Self::Function(_)) |
+            (Self::Closure(_), Self::Closure(_)) |
+            (Self::Native(_), Self::Native(_)) |
+            (Self::Class(_), Self::Class(_)) |
+            (Self::Instance(_), Self::Instance(_)) |
+            (Self::BoundMethod(_), Self::BoundMethod(_)) |
+            (Self::Iterator(_), Self::Iterator(_)) |
+            (Self::Range(_), Self::Range(_)) |
+            (Self::List(_), Self::List(_)) |
+            (Self::Map(_), Self::Map(_)) |
             (Self::None, _) |
             (_, Self::None) => return Ok(()),
             _ => Err(format!(
@@ -96,6 +217,21 @@ impl Literal {
         }
     }
 
+    pub fn contains(&self, value: &Self) -> Result<bool, String> {
+        match (self, value) {
+            (Self::Range(range), Self::Int(v)) => Ok(range.contains(*v)),
+            (Self::Range(_), _) => Ok(false),
+            (Self::List(list), v) => Ok(list.borrow().contains(v)),
+            (Self::Map(map), v) => map.borrow().contains_key(v),
+            (Self::String(string), Self::String(v)) => Ok(string.contains(v.as_str())),
+            _ => Err(format!(
+                "Cannot check if type {} is in type {}",
+                value.type_name(),
+                self.type_name()
+            )),
+        }
+    }
+
     pub fn comparable(&self, rhs: &Self) -> Result<(), String> {
         match (self, rhs) {
             (Self::Int(_), Self::Int(_)) |
@@ -115,16 +251,22 @@ impl Literal {
     }
 }
 
+// overflowing integer arithmetic is an error rather than a wrap or a panic
+fn checked(value: Option<isize>) -> Result<Literal, String> {
+    value
+        .map(Literal::Int)
+        .ok_or("integer overflow".to_string())
+}
+
 impl Add for Literal {
     type Output = Result<Self, String>;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (&self, &rhs) {
-            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a + b)),
+            (Self::Int(a), Self::Int(b)) => checked(a.checked_add(*b)),
             (Self::Float(a), Self::Float(b)) => Ok(Self::Float(a + b)),
             (Self::Float(a), Self::Int(b)) => Ok(Self::Float(a + *b as f64)),
             (Self::Int(a), Self::Float(b)) => Ok(Self::Float(*a as f64 + b)),
-            (Self::String(a), Self::String(b)) => Ok(Self::String(a.to_owned() + b)),
             _ => Err(format!(
                 "Cannot add types {} and {}",
                 self.type_name(),
@@ -139,7 +281,7 @@ impl Sub for Literal {
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (&self, &rhs) {
-            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a - b)),
+            (Self::Int(a), Self::Int(b)) => checked(a.checked_sub(*b)),
             (Self::Float(a), Self::Float(b)) => Ok(Self::Float(a - b)),
             (Self::Float(a), Self::Int(b)) => Ok(Self::Float(a - *b as f64)),
             (Self::Int(a), Self::Float(b)) => Ok(Self::Float(*a as f64 - b)),
@@ -157,11 +299,10 @@ impl Mul for Literal {
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (&self, &rhs) {
-            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a * b)),
+            (Self::Int(a), Self::Int(b)) => checked(a.checked_mul(*b)),
             (Self::Float(a), Self::Float(b)) => Ok(Self::Float(a * b)),
             (Self::Float(a), Self::Int(b)) => Ok(Self::Float(a * *b as f64)),
             (Self::Int(a), Self::Float(b)) => Ok(Self::Float(*a as f64 * b)),
-            (Self::String(a), Self::Int(b)) => Ok(Self::String(a.repeat(*b as usize))),
             _ => Err(format!(
                 "Cannot multiply types {} and {}",
                 self.type_name(),
@@ -176,7 +317,8 @@ impl Div for Literal {
 
     fn div(self, rhs: Self) -> Self::Output {
         match (&self, &rhs) {
-            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a / b)),
+            (Self::Int(_), Self::Int(0)) => Err("division by zero".to_string()),
+            (Self::Int(a), Self::Int(b)) => checked(a.checked_div(*b)),
             (Self::Float(a), Self::Float(b)) => Ok(Self::Float(a / b)),
             (Self::Float(a), Self::Int(b)) => Ok(Self::Float(a / *b as f64)),
             (Self::Int(a), Self::Float(b)) => Ok(Self::Float(*a as f64 / b)),
@@ -189,6 +331,126 @@ impl Div for Literal {
     }
 }
 
+impl Rem for Literal {
+    type Output = Result<Self, String>;
+
+    // floored, the result takes the sign of the divisor
+    fn rem(self, rhs: Self) -> Self::Output {
+        match (&self, &rhs) {
+            (Self::Int(_), Self::Int(0)) => Err("modulo by zero".to_string()),
+            (Self::Int(a), Self::Int(b)) => {
+                let rem = a.wrapping_rem(*b);
+                if rem != 0 && (rem < 0) != (*b < 0) {
+                    return Ok(Self::Int(rem + b));
+                }
+                Ok(Self::Int(rem))
+            }
+            (Self::Float(a), Self::Float(b)) => floored_rem(*a, *b),
+            (Self::Float(a), Self::Int(b)) => floored_rem(*a, *b as f64),
+            (Self::Int(a), Self::Float(b)) => floored_rem(*a as f64, *b),
+            _ => Err(format!(
+                "Cannot modulo types {} and {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl BitAnd for Literal {
+    type Output = Result<Self, String>;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        match (&self, &rhs) {
+            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a & b)),
+            _ => Err(format!(
+                "Cannot bitwise and types {} and {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl BitOr for Literal {
+    type Output = Result<Self, String>;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        match (&self, &rhs) {
+            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a | b)),
+            _ => Err(format!(
+                "Cannot bitwise or types {} and {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl BitXor for Literal {
+    type Output = Result<Self, String>;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        match (&self, &rhs) {
+            (Self::Int(a), Self::Int(b)) => Ok(Self::Int(a ^ b)),
+            _ => Err(format!(
+                "Cannot bitwise xor types {} and {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl Shl for Literal {
+    type Output = Result<Self, String>;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        match (&self, &rhs) {
+            (Self::Int(a), Self::Int(b)) => u32::try_from(*b)
+                .ok()
+                .and_then(|b| a.checked_shl(b))
+                .map(Self::Int)
+                .ok_or(format!("Shift amount {b} is out of range")),
+            _ => Err(format!(
+                "Cannot shift types {} and {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+impl Shr for Literal {
+    type Output = Result<Self, String>;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        match (&self, &rhs) {
+            (Self::Int(a), Self::Int(b)) => u32::try_from(*b)
+                .ok()
+                .and_then(|b| a.checked_shr(b))
+                .map(Self::Int)
+                .ok_or(format!("Shift amount {b} is out of range")),
+            _ => Err(format!(
+                "Cannot shift types {} and {}",
+                self.type_name(),
+                rhs.type_name()
+            )),
+        }
+    }
+}
+
+fn floored_rem(a: f64, b: f64) -> Result<Literal, String> {
+    if b == 0. {
+        return Err("modulo by zero".to_string());
+    }
+    let rem = a % b;
+    if rem != 0. && (rem < 0.) != (b < 0.) {
+        return Ok(Literal::Float(rem + b));
+    }
+    Ok(Literal::Float(rem))
+}
+
 impl PartialEq for Literal {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -199,7 +461,17 @@ impl PartialEq for Literal {
             (Self::Bool(a), Self::Bool(b)) => a == b,
             (Self::Bool(a), Self::Int(b)) => *a as isize == *b,
             (Self::Int(a), Self::Bool(b)) => *a == *b as isize,
-            (Self::String(a), Self::String(b)) => a.eq(b),
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Function(a), Self::Function(b)) => Gc::ptr_eq(a, b),
+            (Self::Closure(a), Self::Closure(b)) => Gc::ptr_eq(a, b),
+            (Self::Native(a), Self::Native(b)) => Gc::ptr_eq(a, b),
+            (Self::Class(a), Self::Class(b)) => Gc::ptr_eq(a, b),
+            (Self::Instance(a), Self::Instance(b)) => Gc::ptr_eq(a, b),
+            (Self::BoundMethod(a), Self::BoundMethod(b)) => Gc::ptr_eq(a, b),
+            (Self::Iterator(a), Self::Iterator(b)) => Gc::ptr_eq(a, b),
+            (Self::Range(a), Self::Range(b)) => a == b,
+            (Self::List(a), Self::List(b)) => Gc::ptr_eq(a, b),
+            (Self::Map(a), Self::Map(b)) => Gc::ptr_eq(a, b),
             (Self::None, Self::None) => true,
             _ => false,
         }
@@ -216,7 +488,7 @@ impl PartialOrd for Literal {
             (Self::Bool(a), Self::Bool(b)) => a < b,
             (Self::Bool(a), Self::Int(b)) => (*a as isize) < *b,
             (Self::Int(a), Self::Bool(b)) => *a < *b as isize,
-            (Self::String(a), Self::String(b)) => a.len() < b.len(),
+            (Self::String(a), Self::String(b)) => **a < **b,
             _ => false,
         }
     }
@@ -230,7 +502,7 @@ impl PartialOrd for Literal {
             (Self::Bool(a), Self::Bool(b)) => a <= b,
             (Self::Bool(a), Self::Int(b)) => (*a as isize) <= *b,
             (Self::Int(a), Self::Bool(b)) => *a <= *b as isize,
-            (Self::String(a), Self::String(b)) => a.len() <= b.len(),
+            (Self::String(a), Self::String(b)) => **a <= **b,
             _ => false,
         }
     }
@@ -243,7 +515,7 @@ impl PartialOrd for Literal {
             (Self::Int(a), Self::Float(b)) => (*a as f64) > *b,
             (Self::Bool(a), Self::Bool(b)) => a > b,
             (Self::Bool(a), Self::Int(b)) => (*a as isize) > *b,
-            (Self::String(a), Self::String(b)) => a.len() > b.len(),
+            (Self::String(a), Self::String(b)) => **a > **b,
             (Self::Int(a), Self::Bool(b)) => *a > *b as isize,
             _ => false,
         }
@@ -258,7 +530,7 @@ impl PartialOrd for Literal {
             (Self::Bool(a), Self::Bool(b)) => a >= b,
             (Self::Bool(a), Self::Int(b)) => (*a as isize) >= *b,
             (Self::Int(a), Self::Bool(b)) => *a >= *b as isize,
-            (Self::String(a), Self::String(b)) => a.len() >= b.len(),
+            (Self::String(a), Self::String(b)) => **a >= **b,
             _ => false,
         }
     }
@@ -0,0 +1,102 @@
+use std::{collections::HashMap, fmt::Display, mem};
+
+use super::literal::Literal;
+use crate::common::gc::{Gc, Trace, Tracer};
+
+// hashable form of a key, numbers that compare equal hash the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum HashKey {
+    None,
+    Int(isize),
+    Float(u64),
+    String(Gc<String>),
+}
+
+impl TryFrom<&Literal> for HashKey {
+    type Error = String;
+
+    fn try_from(value: &Literal) -> Result<Self, Self::Error> {
+        match value {
+            Literal::None => Ok(Self::None),
+            Literal::Int(v) => Ok(Self::Int(*v)),
+            Literal::Bool(v) => Ok(Self::Int(*v as isize)),
+            Literal::Float(v) if v.fract() == 0. && v.abs() < isize::MAX as f64 => {
+                Ok(Self::Int(*v as isize))
+            }
+            Literal::Float(v) => Ok(Self::Float(v.to_bits())),
+            Literal::String(v) => Ok(Self::String(v.clone())),
+            _ => Err(format!("type {} is not hashable", value.type_name())),
+        }
+    }
+}
+
+// keeps insertion order so iteration and printing are stable
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Literal, Literal)>,
+    indices: HashMap<HashKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &Literal) -> Result<Option<Literal>, String> {
+        let key = HashKey::try_from(key)?;
+        Ok(self
+            .indices
+            .get(&key)
+            .map(|index| self.entries[*index].1.clone()))
+    }
+
+    pub fn insert(&mut self, key: Literal, value: Literal) -> Result<(), String> {
+        let hash_key = HashKey::try_from(&key)?;
+        match self.indices.get(&hash_key) {
+            Some(index) => self.entries[*index].1 = value,
+            None => {
+                self.indices.insert(hash_key, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains_key(&self, key: &Literal) -> Result<bool, String> {
+        Ok(self.indices.contains_key(&HashKey::try_from(key)?))
+    }
+
+    pub fn keys(&self) -> Vec<Literal> {
+        self.entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Trace for Map {
+    fn trace(&self, tracer: &mut Tracer) {
+        for (key, value) in self.entries.iter() {
+            key.trace(tracer);
+            value.trace(tracer);
+        }
+    }
+
+    fn extra_size(&self) -> usize {
+        self.entries.capacity() * mem::size_of::<(Literal, Literal)>()
+    }
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{key}: {value}")?;
+        }
+        write!(f, "}}")
+    }
+}
@@ -2,3 +2,9 @@ pub mod interpret_result;
 pub mod literal;
 pub mod op_codes;
 pub mod function;
+pub mod closure;
+pub mod class;
+pub mod iter;
+pub mod range;
+pub mod map;
+pub mod native;
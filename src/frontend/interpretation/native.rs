@@ -0,0 +1,39 @@
+use std::fmt::{Debug, Display};
+
+use super::literal::Literal;
+use crate::common::gc::{Trace, Tracer};
+
+pub type NativeFn = fn(&[Literal]) -> Result<Literal, String>;
+
+// function implemented in rust and exposed as a global
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl Native {
+    pub fn new(name: &'static str, arity: usize, function: NativeFn) -> Self {
+        Self {
+            name,
+            arity,
+            function,
+        }
+    }
+}
+
+impl Trace for Native {
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+impl Display for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
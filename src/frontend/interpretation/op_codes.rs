@@ -5,6 +5,7 @@ pub enum OpCodes {
     Return,
     //       index
     Constant(usize),
+    // slot in the global table rather than a constant
     DefGlobal(usize),
     GetGlobal(usize),
     SetGlobal(usize),
@@ -13,11 +14,39 @@ pub enum OpCodes {
     JumpFalse(usize),
     Jump(usize),
     Loop(usize),
+    Call(usize),
+    ForIter(usize),
+    Closure(usize),
+    GetUpvalue(usize),
+    SetUpvalue(usize),
+    CloseUpvalue,
+    GetIter,
+    Class(usize),
+    Method(usize),
+    Inherit,
+    Field(usize),
+    StaticField(usize),
+    Public(usize),
+    GetSuper(usize),
+    GetProperty(usize),
+    SetProperty(usize),
+    BuildList(usize),
+    BuildMap(usize),
+    Concat(usize),
+    GetIndex,
+    SetIndex,
     Negate,
     Add,
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    BitNot,
     None,
     True,
     False,
@@ -28,7 +57,9 @@ pub enum OpCodes {
     GreaterEquals,
     Less,
     LessEquals,
-    Ternary,
+    Range,
+    RangeInclusive,
+    In,
     Print,
     Pop,
 }
@@ -38,6 +69,7 @@ impl OpCodes {
         match self {
             Self::JumpFalse(_) => Self::JumpFalse(offset),
             Self::Jump(_) => Self::Jump(offset),
+            Self::ForIter(_) => Self::ForIter(offset),
             _ => unreachable!("bad"),
         }
     }
@@ -56,11 +88,39 @@ impl Display for OpCodes {
             Self::JumpFalse(_) => "OP_JUMP_FALSE",
             Self::Loop(_) => "OP_LOOP",
             Self::Jump(_) => "OP_JUMP",
+            Self::Call(_) => "OP_CALL",
+            Self::ForIter(_) => "OP_FOR_ITER",
+            Self::GetIter => "OP_GET_ITER",
+            Self::Closure(_) => "OP_CLOSURE",
+            Self::GetUpvalue(_) => "OP_UPVALUE_GET",
+            Self::SetUpvalue(_) => "OP_UPVALUE_SET",
+            Self::CloseUpvalue => "OP_UPVALUE_CLOSE",
+            Self::Class(_) => "OP_CLASS",
+            Self::Method(_) => "OP_METHOD",
+            Self::Inherit => "OP_INHERIT",
+            Self::Field(_) => "OP_FIELD",
+            Self::StaticField(_) => "OP_STATIC_FIELD",
+            Self::Public(_) => "OP_PUBLIC",
+            Self::GetSuper(_) => "OP_SUPER_GET",
+            Self::GetProperty(_) => "OP_PROPERTY_GET",
+            Self::SetProperty(_) => "OP_PROPERTY_SET",
+            Self::BuildList(_) => "OP_BUILD_LIST",
+            Self::BuildMap(_) => "OP_BUILD_MAP",
+            Self::Concat(_) => "OP_CONCAT",
+            Self::GetIndex => "OP_INDEX_GET",
+            Self::SetIndex => "OP_INDEX_SET",
             Self::Negate => "OP_NEGATE",
             Self::Add => "OP_ADD",
             Self::Subtract => "OP_SUBTRACT",
             Self::Multiply => "OP_MULTIPLY",
             Self::Divide => "OP_DIVIDE",
+            Self::Modulo => "OP_MODULO",
+            Self::BitAnd => "OP_BIT_AND",
+            Self::BitOr => "OP_BIT_OR",
+            Self::BitXor => "OP_BIT_XOR",
+            Self::ShiftLeft => "OP_SHIFT_LEFT",
+            Self::ShiftRight => "OP_SHIFT_RIGHT",
+            Self::BitNot => "OP_BIT_NOT",
             Self::None => "OP_NONE",
             Self::True => "OP_TRUE",
             Self::False => "OP_FALSE",
@@ -71,7 +131,9 @@ impl Display for OpCodes {
             Self::GreaterEquals => "OP_GREATER_EQUAL",
             Self::Less => "OP_LESS",
             Self::LessEquals => "OP_LESS_EQUAL",
-            Self::Ternary => "OP_TERNARY",
+            Self::Range => "OP_RANGE",
+            Self::RangeInclusive => "OP_RANGE_INCLUSIVE",
+            Self::In => "OP_IN",
             Self::Print => "OP_PRINT",
             Self::Pop => "OP_POP",
         };
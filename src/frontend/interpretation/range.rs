@@ -0,0 +1,54 @@
+use std::fmt::Display;
+
+// integer range, never materialized into a collection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: isize,
+    pub end: isize,
+    pub step: isize,
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn new(start: isize, end: isize, step: isize, inclusive: bool) -> Self {
+        Self {
+            start,
+            end,
+            step,
+            inclusive,
+        }
+    }
+
+    // whether `value` lies within the bounds, ignoring the step
+    pub fn in_bounds(&self, value: isize) -> bool {
+        match (self.step > 0, self.inclusive) {
+            (true, true) => value >= self.start && value <= self.end,
+            (true, false) => value >= self.start && value < self.end,
+            (false, true) => value <= self.start && value >= self.end,
+            (false, false) => value <= self.start && value > self.end,
+        }
+    }
+
+    pub fn contains(&self, value: isize) -> bool {
+        // unsigned distance so ranges spanning most of `isize` cannot overflow
+        self.in_bounds(value)
+            && value
+                .abs_diff(self.start)
+                .is_multiple_of(self.step.unsigned_abs())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.in_bounds(self.start)
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", self.start, op, self.end)?;
+        if self.step != 1 {
+            write!(f, " step {}", self.step)?;
+        }
+        Ok(())
+    }
+}
@@ -8,6 +8,8 @@ pub struct Lexer {
     location: Location,
     start: usize,
     current: usize,
+    // brace depth of each interpolation we are inside of
+    format_depths: Vec<usize>,
     options: Options
 }
 
@@ -19,13 +21,19 @@ impl Lexer {
             location: Location::default(),
             start: 0,
             current: 0,
+            format_depths: Vec::new(),
             options
         }
     }
 
     pub fn tokens(&mut self) -> Result<Vec<Token>, ()> {
         let mut stream: Vec<Token> = Vec::new();
-        while !self.is_at_end() {
+        loop {
+            // a trailing comment leaves nothing to lex
+            self.skip_whitespace()?;
+            if self.is_at_end() {
+                break;
+            }
             stream.push(self.token()?);
         }
         stream.push(Token::eof(Span::new(self.file_path.clone(), self.location)));
@@ -37,7 +45,6 @@ impl Lexer {
     }
 
     pub fn token(&mut self) -> LexerResult {
-        self.skip_whitespace();
         self.start = self.current;
         self.location.start = self.location.end;
 
@@ -46,12 +53,39 @@ impl Lexer {
         match c {
             '(' => return Ok(self.make_token(TokenKind::LeftParen)),
             ')' => return Ok(self.make_token(TokenKind::RightParen)),
-            '{' => return Ok(self.make_token(TokenKind::LeftBrace)),
-            '}' => return Ok(self.make_token(TokenKind::RightBrace)),
+            '{' => {
+                if let Some(depth) = self.format_depths.last_mut() {
+                    *depth += 1;
+                }
+                return Ok(self.make_token(TokenKind::LeftBrace));
+            }
+            '}' => {
+                match self.format_depths.last_mut() {
+                    // closes an interpolation, the rest is string again
+                    Some(0) => {
+                        self.format_depths.pop();
+                        return self.format_string(false);
+                    }
+                    Some(depth) => *depth -= 1,
+                    None => (),
+                }
+                return Ok(self.make_token(TokenKind::RightBrace));
+            }
             '[' => return Ok(self.make_token(TokenKind::LeftBracket)),
             ']' => return Ok(self.make_token(TokenKind::RightBracket)),
             ',' => return Ok(self.make_token(TokenKind::Comma)),
-            '.' => return Ok(self.make_token(TokenKind::Dot)),
+            '.' => {
+                let token = if self.is_match('.') {
+                    if self.is_match('=') {
+                        TokenKind::DotDotEqual
+                    } else {
+                        TokenKind::DotDot
+                    }
+                } else {
+                    TokenKind::Dot
+                };
+                return Ok(self.make_token(token));
+            }
             '-' => {
                 let token = if self.is_match('=') {
                     TokenKind::MinusEqual
@@ -102,6 +136,8 @@ impl Lexer {
             '=' => {
                 let token = if self.is_match('=') {
                     TokenKind::Equals
+                } else if self.is_match('>') {
+                    TokenKind::Arrow
                 } else {
                     TokenKind::Assign
                 };
@@ -110,6 +146,8 @@ impl Lexer {
             '<' => {
                 let token = if self.is_match('=') {
                     TokenKind::LessEqual
+                } else if self.is_match('<') {
+                    TokenKind::ShiftLeft
                 } else {
                     TokenKind::Less
                 };
@@ -118,6 +156,8 @@ impl Lexer {
             '>' => {
                 let token = if self.is_match('=') {
                     TokenKind::GreaterEqual
+                } else if self.is_match('>') {
+                    TokenKind::ShiftRight
                 } else {
                     TokenKind::Greater
                 };
@@ -147,7 +187,13 @@ impl Lexer {
             '^' => {
                 return Ok(self.make_token(TokenKind::Caret));
             }
+            '~' => return Ok(self.make_token(TokenKind::Tilde)),
             '"' | '\'' => return self.string(c),
+            '`' => return self.format_string(true),
+            'r' if matches!(self.peek(), Some('"') | Some('\'')) => {
+                let starter = self.advance();
+                return self.raw_string(starter);
+            }
 
             '0'..='9' => return self.number(),
 
@@ -165,7 +211,7 @@ impl Lexer {
         )
     }
 
-    fn skip_whitespace(&mut self) {
+    fn skip_whitespace(&mut self) -> Result<(), ()> {
         loop {
             match self.peek() {
                 Some(' ') | Some('\r') | Some('\t') => {
@@ -182,12 +228,49 @@ impl Lexer {
                             self.advance();
                         }
                     } else if self.peek_next() == Some('*') {
-                        // self.block_comment()?;
+                        self.block_comment()?;
                     } else { break }
                 }
                 _ => break,
             };
         }
+        Ok(())
+    }
+
+    // block comments nest, `/* a /* b */ c */` is a single comment
+    fn block_comment(&mut self) -> Result<(), ()> {
+        let opening = Location::new(self.location.line, self.location.end, self.location.end + 2);
+        self.advance();
+        self.advance();
+
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.peek(), self.peek_next()) {
+                (None, _) => {
+                    error_at!(&Span::new(self.file_path.clone(), opening), "unterminated block comment");
+                    return Err(());
+                }
+                (Some('/'), Some('*')) => {
+                    self.advance();
+                    self.advance();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.advance();
+                    self.advance();
+                    depth -= 1;
+                }
+                (Some('\n'), _) => {
+                    self.advance();
+                    self.reset_loc();
+                    self.location.line += 1;
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+        Ok(())
     }
 
     fn identifier(&mut self) -> LexerResult {
@@ -206,7 +289,34 @@ impl Lexer {
         Ok(self.make_token(kind))
     }
 
+    // the lexeme keeps its quotes but has escapes already decoded
     fn string(&mut self, starter: char) -> LexerResult {
+        let mut text = String::new();
+        while self.peek() != Some(starter) && !self.is_at_end() {
+            match self.advance() {
+                '\\' => text.push(self.escape(false)?),
+                ch => {
+                    if ch == '\n' {
+                        self.location.line += 1;
+                    }
+                    text.push(ch);
+                }
+            }
+        }
+
+        if self.is_at_end() {
+            self.location.end = self.location.start + 1;
+            error_at!(&self.create_span(), "unterminated string");
+            return Err(());
+        }
+        self.advance();
+
+        let lexeme = format!("{starter}{text}{starter}");
+        Ok(Token::new(TokenKind::String, self.create_span(), lexeme))
+    }
+
+    // `r"..."`, everything up to the closing quote is taken verbatim
+    fn raw_string(&mut self, starter: char) -> LexerResult {
         while self.peek() != Some(starter) && !self.is_at_end() {
             if self.peek() == Some('\n') {
                 self.location.line += 1;
@@ -221,28 +331,111 @@ impl Lexer {
         }
         self.advance();
 
-        Ok(self.make_token(TokenKind::String))
+        // drop the leading `r` so the compiler sees a normal quoted string
+        let lexeme: String = self.source[self.start + 1..self.current].iter().collect();
+        Ok(Token::new(TokenKind::String, self.create_span(), lexeme))
+    }
+
+    // expects the backslash to be consumed already
+    fn escape(&mut self, in_format: bool) -> Result<char, ()> {
+        let start = self.location.end - 1;
+        let Some(ch) = self.peek() else {
+            self.escape_error(start, "unterminated escape sequence");
+            return Err(());
+        };
+        self.advance();
+
+        match ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' | '"' | '\'' => Ok(ch),
+            '{' | '}' | '`' if in_format => Ok(ch),
+            'u' => self.unicode_escape(start),
+            _ => {
+                self.escape_error(start, format!("invalid escape sequence `\\{ch}`").as_str());
+                Err(())
+            }
+        }
     }
 
-    // fn format_string(&mut self) -> LexerResult {
-    //     while self.peek() != Some('`') && !self.is_at_end() {
-    //         match self.peek() {
-    //             Some('\n') => self.location.line += 1,
-    //             Some('{') => (),
-    //             _ => ()
-    //         };
-    //         self.advance();
-    //     }
-    //
-    //     if self.is_at_end() {
-    //         self.location.end = self.location.start + 1;
-    //         error!(Span::new(self.file_path, self.location),"unterminated string");
-    //         return Err(())
-    //     }
-    //     self.advance();
-    //
-    //     Ok(self.make_token(TokenKind::FormatString))
-    // }
+    // `\u{1F600}`, one to six hex digits
+    fn unicode_escape(&mut self, start: usize) -> Result<char, ()> {
+        if !self.is_match('{') {
+            self.escape_error(start, "expected `{` after `\\u`");
+            return Err(());
+        }
+
+        let mut hex = String::new();
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_hexdigit() {
+                break;
+            }
+            hex.push(self.advance());
+        }
+
+        if !self.is_match('}') {
+            self.escape_error(start, "expected `}` to close unicode escape");
+            return Err(());
+        }
+
+        let code = u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() <= 6);
+        let Some(ch) = code.and_then(char::from_u32) else {
+            self.escape_error(start, format!("invalid unicode escape `\\u{{{hex}}}`").as_str());
+            return Err(());
+        };
+        Ok(ch)
+    }
+
+    fn escape_error(&self, start: usize, message: &str) {
+        let location = Location::new(self.location.line, start, self.location.end);
+        error_at!(&Span::new(self.file_path.clone(), location), "{message}");
+    }
+
+    // Scans up to the next interpolation or the closing backtick. A string is lexed as
+    // `FormatString`, then `FormatStringPart` after every inner interpolation and
+    // `FormatStringEnd` after the last one. Without interpolations it is a plain `String`.
+    // Lexemes hold the text with escapes already decoded, `\{`, `\}` and `\`` included.
+    fn format_string(&mut self, head: bool) -> LexerResult {
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => {
+                    self.location.end = self.location.start + 1;
+                    error_at!(&self.create_span(), "unterminated format string");
+                    return Err(());
+                }
+                Some('`') => {
+                    self.advance();
+                    if head {
+                        let lexeme = format!("`{text}`");
+                        return Ok(Token::new(TokenKind::String, self.create_span(), lexeme));
+                    }
+                    return Ok(Token::new(TokenKind::FormatStringEnd, self.create_span(), text));
+                }
+                Some('{') => {
+                    self.advance();
+                    self.format_depths.push(0);
+                    let kind = if head {
+                        TokenKind::FormatString
+                    } else {
+                        TokenKind::FormatStringPart
+                    };
+                    return Ok(Token::new(kind, self.create_span(), text));
+                }
+                Some('\\') => {
+                    self.advance();
+                    text.push(self.escape(true)?);
+                }
+                Some('\n') => {
+                    self.location.line += 1;
+                    text.push(self.advance());
+                }
+                Some(_) => text.push(self.advance()),
+            }
+        }
+    }
 
     fn number(&mut self) -> LexerResult {
         while let Some(ch) = self.peek() {
@@ -252,7 +445,8 @@ impl Lexer {
             self.advance();
         }
 
-        if self.peek() == Some('.') {
+        // `0..10` is a range, not the float `0.`
+        if self.peek() == Some('.') && self.peek_next() != Some('.') {
             self.advance();
             while let Some(ch) = self.peek() {
                 if !ch.is_ascii_digit() {
@@ -331,6 +525,7 @@ impl Lexer {
             "else" => Some(TokenKind::Else),
             "elif" => Some(TokenKind::Elif),
             "this" => Some(TokenKind::This),
+            "super" => Some(TokenKind::Super),
             "true" => Some(TokenKind::True),
             "false" => Some(TokenKind::False),
             "class" => Some(TokenKind::Class),
@@ -1,16 +1,16 @@
-use super::tokenization::token::Token;
-
 pub struct Local<'token> {
-    pub name: &'token Token,
+    pub name: &'token str,
     pub depth: usize,
     pub initialized: bool,
+    pub captured: bool,
 }
 
 impl<'token> Local<'token> {
-    pub fn new(token: &'token Token, depth: usize) -> Self {
+    pub fn new(name: &'token str, depth: usize) -> Self {
         Local {
-            name: token,
+            name,
             initialized: false,
+            captured: false,
             depth,
         }
     }
@@ -1,12 +1,12 @@
 use super::{precedence::Precedence, tokenization::tokenkind::TokenKind};
 
-pub type Rule = u16;
-// Probably wont need postfix but whatever
-// 4 bits postfix, 4 bits infix, 4 bits prefix, 4 bits precedence
-pub const PRECEDENCE_MASK: u16 = 0x000F;
-pub const PREFIX_MASK: u16 = 0x00F0;
-pub const INFIX_MASK: u16 = 0x0F00;
-pub const POSTFIX_MASK: u16 = 0xF000;
+pub type Rule = u32;
+// 8 bits precedence, 4 bits postfix, 4 bits infix, 4 bits prefix, 4 bits unused
+pub const PRECEDENCE_MASK: u32 = 0xFF0000;
+pub const PRECEDENCE_SHIFT: u32 = 16;
+pub const PREFIX_MASK: u32 = 0x00F0;
+pub const INFIX_MASK: u32 = 0x0F00;
+pub const POSTFIX_MASK: u32 = 0xF000;
 
 #[derive(Debug)]
 pub enum RuleFn {
@@ -19,16 +19,30 @@ pub enum RuleFn {
     Grouping,
     Unary,
     Variable,
+    Lambda,
+    This,
+    Super,
+    List,
+    Map,
+    Increment,
+    FormatString,
 
     // infix
     Binary,
     Ternary,
     And,
-    Or
+    Or,
+    Call,
+    Dot,
+    Range,
+    Index,
+
+    // postfix
+    PostIncrement,
 }
 
-impl From<u16> for RuleFn {
-    fn from(value: u16) -> Self {
+impl From<u32> for RuleFn {
+    fn from(value: u32) -> Self {
         match value {
             0 => Self::None,
 
@@ -38,18 +52,31 @@ impl From<u16> for RuleFn {
             _ if 0x40 == value => Self::Unary,
             _ if 0x50 == value => Self::String,
             _ if 0x60 == value => Self::Variable,
+            _ if 0x70 == value => Self::Lambda,
+            _ if 0x80 == value => Self::This,
+            _ if 0x90 == value => Self::Super,
+            _ if 0xA0 == value => Self::List,
+            _ if 0xB0 == value => Self::Map,
+            _ if 0xC0 == value => Self::Increment,
+            _ if 0xD0 == value => Self::FormatString,
 
             _ if 0x100 == value => Self::Binary,
             _ if 0x200 == value => Self::Ternary,
             _ if 0x300 == value => Self::And,
             _ if 0x400 == value => Self::Or,
+            _ if 0x500 == value => Self::Call,
+            _ if 0x600 == value => Self::Dot,
+            _ if 0x700 == value => Self::Range,
+            _ if 0x800 == value => Self::Index,
+
+            _ if 0x1000 == value => Self::PostIncrement,
 
             _ => panic!("Cannot convert {value} to rule."),
         }
     }
 }
 
-impl From<RuleFn> for u16 {
+impl From<RuleFn> for u32 {
     fn from(value: RuleFn) -> Self {
         match value {
             RuleFn::None => 0,
@@ -60,11 +87,24 @@ impl From<RuleFn> for u16 {
             RuleFn::Unary => 0x40,
             RuleFn::String => 0x50,
             RuleFn::Variable => 0x60,
+            RuleFn::Lambda => 0x70,
+            RuleFn::This => 0x80,
+            RuleFn::Super => 0x90,
+            RuleFn::List => 0xA0,
+            RuleFn::Map => 0xB0,
+            RuleFn::Increment => 0xC0,
+            RuleFn::FormatString => 0xD0,
 
             RuleFn::Binary => 0x100,
             RuleFn::Ternary => 0x200,
             RuleFn::And => 0x300,
             RuleFn::Or => 0x400,
+            RuleFn::Call => 0x500,
+            RuleFn::Dot => 0x600,
+            RuleFn::Range => 0x700,
+            RuleFn::Index => 0x800,
+
+            RuleFn::PostIncrement => 0x1000,
         }
     }
 }
@@ -80,34 +120,53 @@ impl ParseRule {
          * 4 - binary
          * */
         match kind {
-            TokenKind::LeftParen => Precedence::None as u16 | u16::from(RuleFn::Grouping),
-            TokenKind::Minus => Precedence::Term as u16 | u16::from(RuleFn::Binary) | u16::from(RuleFn::Unary),
-            TokenKind::Plus => Precedence::Term as u16 | u16::from(RuleFn::Binary),
-            TokenKind::Slash => Precedence::Factor as u16 | u16::from(RuleFn::Binary),
-            TokenKind::Star => Precedence::Factor as u16 | u16::from(RuleFn::Binary),
-            TokenKind::Int => Precedence::None as u16 | u16::from(RuleFn::Number),
-            TokenKind::Float => Precedence::None as u16 | u16::from(RuleFn::Number),
-            TokenKind::None => Precedence::None as u16 | u16::from(RuleFn::Literal),
-            TokenKind::True => Precedence::None as u16 | u16::from(RuleFn::Literal),
-            TokenKind::False => Precedence::None as u16 | u16::from(RuleFn::Literal),
-            TokenKind::Bang => Precedence::None as u16 | u16::from(RuleFn::Unary),
-            TokenKind::BangEqual => Precedence::Equality as u16 | u16::from(RuleFn::Binary),
-            TokenKind::Equals => Precedence::Equality as u16 | u16::from(RuleFn::Binary),
-            TokenKind::Greater => Precedence::Comparison as u16 | u16::from(RuleFn::Binary),
-            TokenKind::GreaterEqual => Precedence::Comparison as u16 | u16::from(RuleFn::Binary),
-            TokenKind::Less => Precedence::Comparison as u16 | u16::from(RuleFn::Binary),
-            TokenKind::LessEqual => Precedence::Comparison as u16 | u16::from(RuleFn::Binary),
-            TokenKind::QuestionMark => Precedence::Ternary as u16 | u16::from(RuleFn::Ternary),
-            TokenKind::String => Precedence::None as u16 | u16::from(RuleFn::String),
-            TokenKind::Identifier => Precedence::None as u16 | u16::from(RuleFn::Variable),
-            TokenKind::And => Precedence::And as u16 | u16::from(RuleFn::And),
-            TokenKind::Or => Precedence::Or as u16 | u16::from(RuleFn::Or),
-            _ => Precedence::None as u16,
+            TokenKind::LeftParen => u32::from(Precedence::Call) | u32::from(RuleFn::Grouping) | u32::from(RuleFn::Call),
+            TokenKind::LeftBracket => u32::from(Precedence::Call) | u32::from(RuleFn::List) | u32::from(RuleFn::Index),
+            TokenKind::LeftBrace => u32::from(Precedence::None) | u32::from(RuleFn::Map),
+            TokenKind::PlusPlus => u32::from(Precedence::Call) | u32::from(RuleFn::PostIncrement) | u32::from(RuleFn::Increment),
+            TokenKind::MinusMinus => u32::from(Precedence::Call) | u32::from(RuleFn::PostIncrement) | u32::from(RuleFn::Increment),
+            TokenKind::Minus => u32::from(Precedence::Term) | u32::from(RuleFn::Binary) | u32::from(RuleFn::Unary),
+            TokenKind::Plus => u32::from(Precedence::Term) | u32::from(RuleFn::Binary),
+            TokenKind::Slash => u32::from(Precedence::Factor) | u32::from(RuleFn::Binary),
+            TokenKind::Star => u32::from(Precedence::Factor) | u32::from(RuleFn::Binary),
+            TokenKind::Ampersand => u32::from(Precedence::BitAnd) | u32::from(RuleFn::Binary),
+            TokenKind::Bar => u32::from(Precedence::BitOr) | u32::from(RuleFn::Binary),
+            TokenKind::Caret => u32::from(Precedence::BitXor) | u32::from(RuleFn::Binary),
+            TokenKind::ShiftLeft => u32::from(Precedence::Shift) | u32::from(RuleFn::Binary),
+            TokenKind::ShiftRight => u32::from(Precedence::Shift) | u32::from(RuleFn::Binary),
+            TokenKind::Tilde => u32::from(Precedence::None) | u32::from(RuleFn::Unary),
+            TokenKind::Modulus => u32::from(Precedence::Factor) | u32::from(RuleFn::Binary),
+            TokenKind::Int => u32::from(Precedence::None) | u32::from(RuleFn::Number),
+            TokenKind::Float => u32::from(Precedence::None) | u32::from(RuleFn::Number),
+            TokenKind::None => u32::from(Precedence::None) | u32::from(RuleFn::Literal),
+            TokenKind::True => u32::from(Precedence::None) | u32::from(RuleFn::Literal),
+            TokenKind::False => u32::from(Precedence::None) | u32::from(RuleFn::Literal),
+            TokenKind::Bang => u32::from(Precedence::None) | u32::from(RuleFn::Unary),
+            TokenKind::BangEqual => u32::from(Precedence::Equality) | u32::from(RuleFn::Binary),
+            TokenKind::Equals => u32::from(Precedence::Equality) | u32::from(RuleFn::Binary),
+            TokenKind::Greater => u32::from(Precedence::Comparison) | u32::from(RuleFn::Binary),
+            TokenKind::GreaterEqual => u32::from(Precedence::Comparison) | u32::from(RuleFn::Binary),
+            TokenKind::Less => u32::from(Precedence::Comparison) | u32::from(RuleFn::Binary),
+            TokenKind::LessEqual => u32::from(Precedence::Comparison) | u32::from(RuleFn::Binary),
+            TokenKind::QuestionMark => u32::from(Precedence::Ternary) | u32::from(RuleFn::Ternary),
+            TokenKind::String => u32::from(Precedence::None) | u32::from(RuleFn::String),
+            TokenKind::FormatString => u32::from(Precedence::None) | u32::from(RuleFn::FormatString),
+            TokenKind::Identifier => u32::from(Precedence::None) | u32::from(RuleFn::Variable),
+            TokenKind::And => u32::from(Precedence::And) | u32::from(RuleFn::And),
+            TokenKind::Or => u32::from(Precedence::Or) | u32::from(RuleFn::Or),
+            TokenKind::Dot => u32::from(Precedence::Call) | u32::from(RuleFn::Dot),
+            TokenKind::DotDot => u32::from(Precedence::Range) | u32::from(RuleFn::Range),
+            TokenKind::DotDotEqual => u32::from(Precedence::Range) | u32::from(RuleFn::Range),
+            TokenKind::In => u32::from(Precedence::Comparison) | u32::from(RuleFn::Binary),
+            TokenKind::This => u32::from(Precedence::None) | u32::from(RuleFn::This),
+            TokenKind::Super => u32::from(Precedence::None) | u32::from(RuleFn::Super),
+            TokenKind::DefLambda => u32::from(Precedence::None) | u32::from(RuleFn::Lambda),
+            _ => u32::from(Precedence::None),
         }
     }
 
     pub fn get_precedence(rule: Rule) -> Precedence {
-        (rule & PRECEDENCE_MASK).into()
+        ((rule & PRECEDENCE_MASK) >> PRECEDENCE_SHIFT).into()
     }
 
     pub fn get_prefix(rule: Rule) -> RuleFn {
@@ -1,12 +1,19 @@
-#[derive(PartialEq, PartialOrd)]
+use super::parse_rule::PRECEDENCE_SHIFT;
+
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
 pub enum Precedence {
     None,
     Assignment,
     Ternary,
     Or,
     And,
+    BitOr,
+    BitXor,
+    BitAnd,
     Equality,
     Comparison,
+    Range,
+    Shift,
     Term,
     Factor,
     Unary,
@@ -14,22 +21,43 @@ pub enum Precedence {
     Primary,
 }
 
-impl From<u16> for Precedence {
-    fn from(value: u16) -> Self {
+impl Precedence {
+    // one level tighter, used for the right operand of left-associative operators
+    pub fn next(self) -> Self {
+        match self {
+            Precedence::Primary => Precedence::Primary,
+            _ => (self as u32 + 1).into(),
+        }
+    }
+}
+
+impl From<u32> for Precedence {
+    fn from(value: u32) -> Self {
         match value {
             0 => Precedence::None,
             1 => Precedence::Assignment,
             2 => Precedence::Ternary,
             3 => Precedence::Or,
             4 => Precedence::And,
-            5 => Precedence::Equality,
-            6 => Precedence::Comparison,
-            7 => Precedence::Term,
-            8 => Precedence::Factor,
-            9 => Precedence::Unary,
-            10 => Precedence::Call,
-            11 => Precedence::Primary,
+            5 => Precedence::BitOr,
+            6 => Precedence::BitXor,
+            7 => Precedence::BitAnd,
+            8 => Precedence::Equality,
+            9 => Precedence::Comparison,
+            10 => Precedence::Range,
+            11 => Precedence::Shift,
+            12 => Precedence::Term,
+            13 => Precedence::Factor,
+            14 => Precedence::Unary,
+            15 => Precedence::Call,
+            16 => Precedence::Primary,
             _ => panic!("Precedence for {value} does not exist"),
         }
     }
 }
+
+impl From<Precedence> for u32 {
+    fn from(value: Precedence) -> Self {
+        (value as u32) << PRECEDENCE_SHIFT
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::{Display, Debug};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Location {
     pub line: u32,
     pub start: usize,
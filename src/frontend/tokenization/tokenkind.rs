@@ -20,6 +20,7 @@ pub enum TokenKind {
     Ampersand,
     Bar,
     Caret,
+    Tilde,
 
     // One or two character tokens.
     Bang,
@@ -32,16 +33,23 @@ pub enum TokenKind {
     PlusPlus,
     MinusMinus,
     Assign,
+    Arrow,
+    DotDot,
+    DotDotEqual,
     Equals,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    ShiftLeft,
+    ShiftRight,
 
     // Literals.
     Identifier,
     String,
     FormatString,
+    FormatStringPart,
+    FormatStringEnd,
     Int,
     Float,
 
@@ -58,6 +66,7 @@ pub enum TokenKind {
     Or,
     Return,
     This,
+    Super,
     True,
     Let,
     None,
@@ -9,6 +9,8 @@ Options:
   -h, --help    Displays this screen.
   -d, --debug   Displays the opcodes and stack values.
   -t, --tokens  Displays lexed tokens.
+  --gc-stats    Displays garbage collector statistics after running.
+  --stress-gc   Collects garbage before every instruction.
 "#;
 
 #[derive(Clone)]
@@ -16,6 +18,8 @@ pub struct Options {
     pub file_path: Rc<Path>,
     pub debug: bool,
     pub print_tokens: bool,
+    pub gc_stats: bool,
+    pub stress_gc: bool,
 }
 
 impl Default for Options {
@@ -23,7 +27,9 @@ impl Default for Options {
         Options {
             file_path: Path::new("").into(),
             debug: false,
-            print_tokens: false
+            print_tokens: false,
+            gc_stats: false,
+            stress_gc: false,
         }
     }
 }
@@ -57,6 +63,8 @@ impl ParseArgs {
                 },
                 "-d" | "--debug" => options.debug = true,
                 "-t" | "--tokens" => options.print_tokens = true,
+                "--gc-stats" => options.gc_stats = true,
+                "--stress-gc" => options.stress_gc = true,
                 _ => options.file_path = Path::new(arg.as_str()).into(),
             }
         }
@@ -1,4 +1,4 @@
-use crate::{frontend::tokenization::token::Token, common::chunk::Chunk};
+use crate::frontend::{interpretation::function::Function, tokenization::token::Token};
 
 pub type LexerResult = Result<Token, ()>;
-pub type CompilerResult<'chunk> = Result<&'chunk Chunk, ()>;
+pub type CompilerResult = Result<Function, ()>;
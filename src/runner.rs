@@ -23,5 +23,8 @@ impl Runner {
         let source = std::fs::read_to_string(self.options.file_path.clone())
             .expect(format!("Unable to read source file: {path}").as_str());
         self.vm.interpret(path.into(), source);
+        if self.options.gc_stats {
+            eprintln!("{}", self.vm.heap());
+        }
     }
 }
@@ -1,6 +1,9 @@
 use std::io::{stdout, Stdout, Write};
 
-use crate::{common::chunk::Chunk, frontend::interpretation::op_codes::OpCodes};
+use crate::{
+    common::chunk::Chunk,
+    frontend::interpretation::{literal::Literal, op_codes::OpCodes},
+};
 
 pub struct Debugger {
     name: String,
@@ -24,54 +27,101 @@ impl Debugger {
         )
         .unwrap();
 
-        for i in 0..chunk.code.len() {
-            self.disassemble_instruction(chunk, i);
+        let mut offset = 0;
+        while offset < chunk.code.len() {
+            offset = self.disassemble_instruction(chunk, offset);
         }
 
         self.stdout.flush().unwrap();
     }
 
-    fn constant_instruction(&mut self, chunk: &Chunk, value: usize, offset: usize) {
-        write!(self.stdout, "{:-16} {offset:4} '", chunk.code[offset]).unwrap();
+    fn constant_instruction(&mut self, chunk: &Chunk, instruction: &OpCodes, value: usize) {
+        write!(self.stdout, "{:-16} {value:4} '", instruction).unwrap();
         writeln!(self.stdout, "{}", chunk.constants[value]).unwrap();
     }
 
-    fn byte_instruction(&mut self, chunk: &Chunk, slot: usize, offset: usize) {
-        writeln!(self.stdout, "{:-16} {slot:4}", chunk.code[offset]).unwrap();
+    fn byte_instruction(&mut self, instruction: &OpCodes, slot: usize) {
+        writeln!(self.stdout, "{:-16} {slot:4}", instruction).unwrap();
     }
 
-    fn simple_instruction(&mut self, chunk: &Chunk, offset: usize) {
-        writeln!(self.stdout, "{}", chunk.code[offset]).unwrap();
+    // jump offsets are printed as the absolute target they land on
+    fn jump_instruction(&mut self, instruction: &OpCodes, target: usize) {
+        writeln!(self.stdout, "{:-16} -> {target:04}", instruction).unwrap();
     }
 
-    pub fn disassemble_instruction(&mut self, chunk: &Chunk, offset: usize) {
-        let instruction = chunk.code[offset].clone();
+    fn closure_instruction(&mut self, chunk: &Chunk, instruction: &OpCodes, value: usize) {
+        self.constant_instruction(chunk, instruction, value);
+        let Literal::Function(function) = &chunk.constants[value] else {
+            return;
+        };
+
+        for upvalue in function.upvalues.iter() {
+            let kind = if upvalue.is_local { "local" } else { "upvalue" };
+            writeln!(
+                self.stdout,
+                "     |                  {kind} {}",
+                upvalue.index
+            )
+            .unwrap();
+        }
+    }
+
+    fn simple_instruction(&mut self, instruction: &OpCodes) {
+        writeln!(self.stdout, "{}", instruction).unwrap();
+    }
+
+    // returns the offset of the next instruction
+    pub fn disassemble_instruction(&mut self, chunk: &Chunk, offset: usize) -> usize {
+        let (instruction, next) = chunk.read(offset);
         write!(self.stdout, "{:04} ", offset).unwrap();
 
         if offset > 0 && chunk.check_previous(offset) {
             write!(self.stdout, "   |  ").unwrap();
         } else {
             if let Some(line) = chunk.get_line(offset) {
-                write!(self.stdout, "{:>4}  ", line.line).unwrap();
+                write!(self.stdout, "{:>4}  ", line.location.line).unwrap();
             }
         }
 
-        match instruction.into() {
-            OpCodes::Constant(value) => self.constant_instruction(chunk, value, offset),
-            OpCodes::DefGlobal(value) => self.constant_instruction(chunk, value, offset),
-            OpCodes::SetGlobal(value) => self.constant_instruction(chunk, value, offset),
-            OpCodes::GetGlobal(value) => self.constant_instruction(chunk, value, offset),
-            OpCodes::SetLocal(value) => self.byte_instruction(chunk, value, offset),
-            OpCodes::GetLocal(value) => self.byte_instruction(chunk, value, offset),
-            OpCodes::JumpFalse(value) => self.byte_instruction(chunk, value, offset),
-            OpCodes::Jump(value) => self.byte_instruction(chunk, value, offset),
-            OpCodes::Loop(value) => self.byte_instruction(chunk, value, offset),
+        match instruction {
+            OpCodes::Constant(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::DefGlobal(value) => self.byte_instruction(&instruction, value),
+            OpCodes::SetGlobal(value) => self.byte_instruction(&instruction, value),
+            OpCodes::GetGlobal(value) => self.byte_instruction(&instruction, value),
+            OpCodes::SetLocal(value) => self.byte_instruction(&instruction, value),
+            OpCodes::GetLocal(value) => self.byte_instruction(&instruction, value),
+            OpCodes::JumpFalse(value) => self.jump_instruction(&instruction, next + value),
+            OpCodes::Jump(value) => self.jump_instruction(&instruction, next + value),
+            OpCodes::Loop(value) => self.jump_instruction(&instruction, next - value),
+            OpCodes::Call(value) => self.byte_instruction(&instruction, value),
+            OpCodes::ForIter(value) => self.jump_instruction(&instruction, next + value),
+            OpCodes::Closure(value) => self.closure_instruction(chunk, &instruction, value),
+            OpCodes::Class(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::Method(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::Field(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::StaticField(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::Public(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::GetSuper(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::GetProperty(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::SetProperty(value) => self.constant_instruction(chunk, &instruction, value),
+            OpCodes::BuildList(value) => self.byte_instruction(&instruction, value),
+            OpCodes::BuildMap(value) => self.byte_instruction(&instruction, value),
+            OpCodes::Concat(value) => self.byte_instruction(&instruction, value),
+            OpCodes::GetUpvalue(value) => self.byte_instruction(&instruction, value),
+            OpCodes::SetUpvalue(value) => self.byte_instruction(&instruction, value),
             OpCodes::Return
             | OpCodes::Negate
             | OpCodes::Add
             | OpCodes::Subtract
             | OpCodes::Multiply
             | OpCodes::Divide
+            | OpCodes::Modulo
+            | OpCodes::BitAnd
+            | OpCodes::BitOr
+            | OpCodes::BitXor
+            | OpCodes::ShiftLeft
+            | OpCodes::ShiftRight
+            | OpCodes::BitNot
             | OpCodes::True
             | OpCodes::False
             | OpCodes::Not
@@ -81,10 +131,18 @@ impl Debugger {
             | OpCodes::GreaterEquals
             | OpCodes::NotEquals
             | OpCodes::Equals
-            | OpCodes::Ternary
+            | OpCodes::Range
+            | OpCodes::RangeInclusive
+            | OpCodes::In
+            | OpCodes::GetIndex
+            | OpCodes::SetIndex
             | OpCodes::Print
             | OpCodes::Pop
-            | OpCodes::None => self.simple_instruction(chunk, offset),
+            | OpCodes::CloseUpvalue
+            | OpCodes::Inherit
+            | OpCodes::GetIter
+            | OpCodes::None => self.simple_instruction(&instruction),
         }
+        next
     }
 }
@@ -114,6 +114,25 @@ impl Log {
         Log::print(level, base);
     }
 
+    // one line per active call, most recent first
+    pub fn stack_trace(level: Level, frames: &[(&str, Span)]) {
+        let mut base = String::from("\x1B[1m\x1B[38;5;012m   = \x1B[38;5;255mstack trace:\x1B[0m");
+        for (depth, (name, span)) in frames.iter().enumerate() {
+            base.push_str(
+                format!(
+                    "\n\x1B[1m\x1B[38;5;012m{:>3}|\x1B[38;5;255m {} \x1B[38;5;012m-->\x1B[38;5;255m {}:{}:{}\x1B[0m",
+                    depth,
+                    name,
+                    span.file,
+                    span.location.line,
+                    span.location.start
+                )
+                .as_str(),
+            );
+        }
+        Log::print(level, base);
+    }
+
     fn print(level: Level, value: String) {
         if level.is_error() {
             eprintln!("{value}");